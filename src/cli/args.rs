@@ -2,8 +2,14 @@ use clap::{builder::PossibleValue, Parser, ValueEnum};
 use std::{env, fs::create_dir_all};
 use std::{fmt::Display, path::PathBuf};
 use terminal_size::{terminal_size, Height, Width};
+use uuid::Uuid;
 
-use crate::cli::io::{colorize, MessageType};
+use crate::{
+    cli::io::{colorize, MessageType},
+    crypto::Cipher,
+    error::Result,
+    pass::{CardExpiry, EntryKind, Hotp, Kind, Totp},
+};
 
 use super::io::bold;
 const ASCII_ART_ABOUT: &str = r#"
@@ -30,14 +36,100 @@ const ASCII_ART_ABOUT: &str = r#"
 const ABOUT: &str = "L🦀CKBOX: A password manager and generator";
 pub const DEFAULT_PASSWORD_FILENAME: &str = "store";
 
-pub fn get_password_store_path(file_name: String) -> anyhow::Result<PathBuf> {
+/// The kind of entry `add` creates, with the fields that only some kinds have.
+#[derive(clap::Args, Debug, PartialEq, Default)]
+pub struct KindArgs {
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Kind::Login,
+        help = "What the entry holds. The password is the text of a note, the card number, the ID number of an identity, the private key or the token."
+    )]
+    pub kind: Kind,
+    #[clap(long, help = "The name on the card. [card]")]
+    pub cardholder: Option<String>,
+    #[clap(long, help = "When the card expires, as MM/YY or MM/YYYY. [card]")]
+    pub expiry: Option<CardExpiry>,
+    #[clap(long, help = "The full name. [identity]")]
+    pub full_name: Option<String>,
+    #[clap(long, help = "The email address. [identity]")]
+    pub email: Option<String>,
+    #[clap(long, help = "The phone number. [identity]")]
+    pub phone: Option<String>,
+    #[clap(long, help = "The postal address. [identity]")]
+    pub address: Option<String>,
+    #[clap(long, help = "The public key. [ssh-key]")]
+    pub public_key: Option<String>,
+    #[clap(long, help = "The id of the key the token belongs to. [api-token]")]
+    pub key_id: Option<String>,
+}
+
+impl KindArgs {
+    /// The entry kind with its fields. Fails naming the first flag that the kind doesn't have.
+    pub fn into_entry_kind(self) -> std::result::Result<EntryKind, String> {
+        let flags = [
+            ("cardholder", self.cardholder.is_some(), Kind::Card),
+            ("expiry", self.expiry.is_some(), Kind::Card),
+            ("full-name", self.full_name.is_some(), Kind::Identity),
+            ("email", self.email.is_some(), Kind::Identity),
+            ("phone", self.phone.is_some(), Kind::Identity),
+            ("address", self.address.is_some(), Kind::Identity),
+            ("public-key", self.public_key.is_some(), Kind::SshKey),
+            ("key-id", self.key_id.is_some(), Kind::ApiToken),
+        ];
+        if let Some((flag, _, kind)) = flags
+            .into_iter()
+            .find(|(_, set, kind)| *set && *kind != self.kind)
+        {
+            let kind = kind
+                .to_possible_value()
+                .map(|value| value.get_name().to_string());
+            return Err(format!(
+                "--{flag} can only be used with --kind {}",
+                kind.unwrap_or_default()
+            ));
+        }
+        Ok(match self.kind {
+            Kind::Login => EntryKind::Login,
+            Kind::Note => EntryKind::SecureNote,
+            Kind::Card => EntryKind::Card {
+                cardholder: self.cardholder,
+                expiry: self.expiry,
+            },
+            Kind::Identity => EntryKind::Identity {
+                full_name: self.full_name,
+                email: self.email,
+                phone: self.phone,
+                address: self.address,
+            },
+            Kind::SshKey => EntryKind::SshKey {
+                public_key: self.public_key,
+            },
+            Kind::ApiToken => EntryKind::ApiToken {
+                key_id: self.key_id,
+            },
+        })
+    }
+}
+
+/// Parses a `--field name=value` argument. The value may be empty, but the name may not.
+fn parse_field(field: &str) -> std::result::Result<(String, String), String> {
+    match field.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected name=value, got `{field}`")),
+    }
+}
+
+pub fn get_password_store_path(file_name: String) -> Result<PathBuf> {
     #[cfg(not(windows))]
     let home_dir = env::var("HOME")?;
     #[cfg(windows)]
     let home_dir = env::var("USERPROFILE")?;
     let home_path = PathBuf::from(home_dir);
     let file_path = home_path.join(".lockbox").join(file_name);
-    create_dir_all(file_path.parent().unwrap())?;
+    if let Some(parent) = file_path.parent() {
+        create_dir_all(parent)?;
+    }
     Ok(file_path)
 }
 
@@ -99,6 +191,19 @@ fn get_about(terminal_size: Option<(Width, Height)>) -> String {
 pub struct Args {
     #[clap(subcommand)]
     pub command: Command,
+    #[clap(
+        long,
+        global = true,
+        help = "A keyfile that is needed along with the master password to open the password store. New password stores created with a keyfile can't be opened without it."
+    )]
+    pub keyfile: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        help = "The cipher that encrypts a new password store. Existing password stores keep their cipher. [default: aes-256-gcm]"
+    )]
+    pub cipher: Option<Cipher>,
 }
 
 #[derive(Debug, Copy, Clone, Parser, PartialEq)]
@@ -152,6 +257,14 @@ pub enum Command {
         username: Option<String>,
         #[clap(short, long, help = "The password to add.")]
         password: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["password", "generate"],
+            help = "Read the password from this file instead, for example a private SSH key or a long note. [Optional]"
+        )]
+        secret_file: Option<PathBuf>,
+        #[clap(flatten)]
+        kind: KindArgs,
         #[clap(
             short,
             long,
@@ -191,6 +304,50 @@ pub enum Command {
             help = "Whether to include numbers in the generated password. [default: true]"
         )]
         numbers: bool,
+        #[clap(
+            long = "url",
+            help = "A URL where the password is used. Repeat for every URL. [Optional]"
+        )]
+        urls: Vec<String>,
+        #[clap(long = "note", help = "Free-text notes about the password. [Optional]")]
+        notes: Option<String>,
+        #[clap(
+            long = "tag",
+            help = "A tag to group the password by. Repeat for every tag. [Optional]"
+        )]
+        tags: Vec<String>,
+        #[clap(
+            long = "field",
+            value_parser = parse_field,
+            help = "A custom field as name=value. Repeat for every field. [Optional]"
+        )]
+        fields: Vec<(String, String)>,
+        #[clap(
+            long = "secret-field",
+            help = "The name of a custom field to conceal. Its value is asked for unless --field sets it. Repeat for every field. [Optional]"
+        )]
+        secret_fields: Vec<String>,
+        #[clap(
+            long,
+            help = "A TOTP secret for two-factor codes, as an otpauth:// URI or the base32 secret. [Optional]"
+        )]
+        totp: Option<Totp>,
+        #[clap(
+            long,
+            help = "An HOTP secret for counter-based codes, as an otpauth:// URI or the base32 secret. [Optional]"
+        )]
+        hotp: Option<Hotp>,
+        #[clap(
+            long,
+            conflicts_with = "keep_both",
+            help = "Replace the password if one already exists for the service and username."
+        )]
+        overwrite: bool,
+        #[clap(
+            long,
+            help = "Add a second password if one already exists for the service and username. It is stored under a new service name like `github (2)`."
+        )]
+        keep_both: bool,
     },
 
     #[clap(
@@ -252,6 +409,13 @@ pub enum Command {
         master: Option<String>,
         #[clap(short, long, default_value_t = false, aliases=&["show", "show-passwords", "reveal"], help="Whether to show the actual passwords or just the service and username information. [default: false]")]
         show_passwords: bool,
+        #[clap(
+            short,
+            long,
+            value_enum,
+            help = "Only list entries of this kind. [Optional]"
+        )]
+        kind: Option<Kind>,
     },
 
     #[clap(
@@ -264,11 +428,18 @@ pub enum Command {
         #[clap(
             short,
             long,
+            required_unless_present = "id",
             help = "The name of the service for which you are removing a password."
         )]
-        service: String,
+        service: Option<String>,
         #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to remove. [Optional]")]
         username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
         #[clap(
             short,
             long,
@@ -287,17 +458,159 @@ pub enum Command {
         #[clap(
             short,
             long,
+            required_unless_present = "id",
             help = "The name of the service for which you are showing a password."
         )]
-        service: String,
+        service: Option<String>,
         #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to show. [Optional]")]
         username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Whether to show concealed custom fields instead of masking them. [default: false]"
+        )]
+        reveal: bool,
+    },
+
+    #[clap(
+        about = "Edit a password in the password manager",
+        long_about = "Use this command to change a password stored in your password manager in place, so it keeps its id and created time, and the old password goes to its history. You can set a new password by typing it or generating one, rename the service, change the username, or replace its URLs, notes and tags. Anything you don't pass stays as it is."
+    )]
+    Edit {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service for which you are editing a password."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to edit. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            num_args = 0..=1,
+            default_missing_value = "",
+            help = "The new password. Pass --password without a value to type it in instead."
+        )]
+        password: Option<String>,
+        #[clap(
+            short,
+            long,
+            conflicts_with = "password",
+            help = "Generate a new random password and copy it to the clipboard."
+        )]
+        generate: bool,
+        #[clap(short, long, default_value_t = Length::Sixteen, help="The length of the generated password.")]
+        length: Length,
+        #[clap(
+            long,
+            default_value_t = false,
+            help = "Whether to include symbols in the generated password. [default: false]"
+        )]
+        symbols: bool,
+        #[clap(long, help = "Rename the service.")]
+        new_service: Option<String>,
+        #[clap(long, help = "Change the username. Pass an empty value to remove it.")]
+        new_username: Option<String>,
+        #[clap(
+            long = "url",
+            help = "Replace the URLs with this one. Repeat for every URL."
+        )]
+        urls: Vec<String>,
+        #[clap(
+            long = "note",
+            help = "Replace the notes. Pass an empty value to remove them."
+        )]
+        notes: Option<String>,
+        #[clap(
+            long = "tag",
+            help = "Replace the tags with this one. Repeat for every tag."
+        )]
+        tags: Vec<String>,
+        #[clap(
+            long = "field",
+            value_parser = parse_field,
+            help = "Set a custom field as name=value, or remove it with name=. Repeat for every field."
+        )]
+        fields: Vec<(String, String)>,
+        #[clap(
+            long = "secret-field",
+            help = "The name of a custom field to conceal. Its value is asked for unless --field sets it. Repeat for every field."
+        )]
+        secret_fields: Vec<String>,
+        #[clap(
+            long,
+            help = "Set the TOTP secret, as an otpauth:// URI or the base32 secret."
+        )]
+        totp: Option<Totp>,
+        #[clap(
+            long,
+            help = "Set the HOTP secret, as an otpauth:// URI or the base32 secret."
+        )]
+        hotp: Option<Hotp>,
+    },
+
+    #[clap(
+        about = "Show the previous passwords of an entry or restore one",
+        long_about = "Use this command to list the passwords an entry had before, most recent first. Every entry remembers up to 10 of them. Use `history restore` with the number of a previous password to make it current again.",
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
+    History {
+        #[clap(subcommand)]
+        command: Option<HistoryCommand>,
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service whose previous passwords you want to see."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
         #[clap(
             short,
             long,
             help = "The master password used to decrypt the password store."
         )]
         master: Option<String>,
+        #[clap(long, default_value_t = false, aliases=&["show", "reveal"], help="Whether to show the previous passwords or hide them. [default: false]")]
+        show_passwords: bool,
     },
 
     Copy {
@@ -306,11 +619,82 @@ pub enum Command {
         #[clap(
             short,
             long,
+            required_unless_present = "id",
             help = "The name of the service for which you are showing a password."
         )]
-        service: String,
+        service: Option<String>,
         #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to show. [Optional]")]
         username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Show the current TOTP code of a password",
+        long_about = "Use this command to get the current two-factor code of a password that has a TOTP secret. The code is copied to the clipboard, and the seconds it stays valid are shown."
+    )]
+    Totp {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service for which you want a code."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want a code for. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(
+        about = "Generate the next HOTP code of a password",
+        long_about = "Use this command to get the next counter-based code of a password that has an HOTP secret. Every call gives a new code and saves the advanced counter, and the code is copied to the clipboard. Use `otp resync` with a few codes from the token if the counters drifted apart.",
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
+    Otp {
+        #[clap(subcommand)]
+        command: Option<OtpCommand>,
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service for which you want a code."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want a code for. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
         #[clap(
             short,
             long,
@@ -340,6 +724,83 @@ pub enum Command {
         new_master: Option<String>,
     },
 
+    #[clap(
+        about = "Benchmark and tune the key derivation function",
+        long_about = "Use this command to benchmark Argon2id on this machine and find parameters that take about the target time to unlock the password store. Pass --apply to re-encrypt the password store with these parameters. This also upgrades older stores from PBKDF2 to Argon2id."
+    )]
+    Kdf {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            default_value_t = 1000,
+            help = "The target time in milliseconds to unlock the password store. [default: 1000]"
+        )]
+        target_ms: u64,
+        #[clap(
+            short,
+            long,
+            default_value_t = false,
+            help = "Whether to re-encrypt the password store with the benchmarked parameters. [default: false]"
+        )]
+        apply: bool,
+    },
+
+    #[clap(
+        about = "Manage the key slots that unlock the password store",
+        long_about = "Use this command to manage the key slots of your password store. The passwords are encrypted with a random key, and every key slot holds a copy of that key that opens with its own password or keyfile. Adding, changing or removing a slot doesn't touch the others."
+    )]
+    Slot {
+        #[clap(subcommand)]
+        command: SlotCommand,
+    },
+
+    #[clap(
+        about = "Generate or use recovery codes for a forgotten master password",
+        long_about = "Use this command to generate one-time recovery codes, or to use one when you forgot your master password. A recovery code unlocks the password store once and makes you set a new master password."
+    )]
+    Recovery {
+        #[clap(subcommand)]
+        command: RecoveryCommand,
+    },
+
+    #[clap(
+        about = "Split the password store key into shares for emergency access",
+        long_about = "Use this command to split the key of your password store into shares with Shamir's secret sharing, so that a threshold of share holders can unlock it together without the master password."
+    )]
+    ShareKey {
+        #[clap(subcommand)]
+        command: ShareKeyCommand,
+    },
+
+    #[clap(
+        about = "List the backups of the password store or roll back to one",
+        long_about = "Use this command to list the encrypted backups that are kept of the previous versions of your password store, or pass --backup to roll back to one of them. The current password store becomes the most recent backup, so a rollback can be undone."
+    )]
+    Restore {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The number of the backup to roll back to, 1 being the most recent one. Lists the backups if not provided."
+        )]
+        backup: Option<usize>,
+    },
+
     #[clap(
         about = "Start an interactive REPL session",
         long_about = "Use this command to start an interactive REPL (Read-Eval-Print Loop) session with your password manager. In this mode, you can enter commands interactively and see their results immediately."
@@ -350,33 +811,275 @@ pub enum Command {
     },
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use rstest::rstest;
-    use tempfile::NamedTempFile;
-
-    #[rstest(
-        input,
-        case(Some((Width(10), Height(10)))),
-        case(Some((Width(1000), Height(10)))),
-        case(Some((Width(10), Height(1000)))),
-        case(Some((Width(50), Height(20)))),
-        case(Some((Width(500), Height(200)))),
-        case(Some((Width(70), Height(40)))),
-        case(None),
+#[derive(Parser, Debug, PartialEq)]
+pub enum SlotCommand {
+    #[clap(
+        about = "Add a key slot",
+        long_about = "Use this command to add a key slot that opens the password store with another password, or with a keyfile when --new-keyfile is given."
     )]
-    fn test_get_about(input: Option<(Width, Height)>) {
-        let received = get_about(input);
-        assert!(received.contains(ABOUT));
-        let ascii_art_lines = ASCII_ART_ABOUT.lines().collect::<Vec<&str>>();
-        let ascii_art_height = ascii_art_lines.len();
-        let ascii_art_width = ascii_art_lines
-            .iter()
-            .map(|line| line.len())
-            .max()
-            .unwrap_or(0);
-        if let Some((Width(w), Height(h))) = input {
+    Add {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(short, long, help = "The password that opens the new key slot.")]
+        new_password: Option<String>,
+        #[clap(
+            short = 'k',
+            long,
+            help = "Open the new key slot with this keyfile instead of a password."
+        )]
+        new_keyfile: Option<PathBuf>,
+        #[clap(
+            short,
+            long,
+            requires = "new_keyfile",
+            help = "Require the new password as well as the keyfile to open the new key slot."
+        )]
+        with_password: bool,
+        #[clap(short, long, help = "A label to recognize the key slot by.")]
+        label: Option<String>,
+    },
+
+    #[clap(about = "List the key slots of the password store")]
+    List {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+    },
+
+    #[clap(about = "Remove a key slot from the password store")]
+    Remove {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The index of the key slot to remove, as shown by `slot list`."
+        )]
+        index: usize,
+    },
+}
+
+#[derive(Parser, Debug, PartialEq)]
+pub enum HistoryCommand {
+    #[clap(
+        about = "Restore a previous password",
+        long_about = "Use this command to make a previous password of an entry current again. The password it replaces is kept in the history, so the restore can be undone the same way."
+    )]
+    Restore {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service whose password you want to restore."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            default_value_t = 1,
+            help = "The number of the previous password to restore, as shown by `history`. 1 is the most recent."
+        )]
+        number: usize,
+    },
+}
+
+#[derive(Parser, Debug, PartialEq)]
+pub enum OtpCommand {
+    #[clap(
+        about = "Resync the HOTP counter with a token",
+        long_about = "Use this command when a token and lockbox give different HOTP codes. Pass a few consecutive codes from the token, in the order it showed them, and the counter moves past the last one."
+    )]
+    Resync {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            required_unless_present = "id",
+            help = "The name of the service whose counter you want to resync."
+        )]
+        service: Option<String>,
+        #[clap(short, long, aliases=&["user"], help="The username associated with the password you want to resync. [Optional]")]
+        username: Option<String>,
+        #[clap(
+            long,
+            conflicts_with_all = ["service", "username"],
+            help = "The id of the password, as shown by `list`. Use it instead of the service and username."
+        )]
+        id: Option<Uuid>,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long = "code",
+            required = true,
+            num_args = 1..,
+            help = "Consecutive codes from the token, oldest first. At least two are needed."
+        )]
+        codes: Vec<String>,
+    },
+}
+
+#[derive(Parser, Debug, PartialEq)]
+pub enum RecoveryCommand {
+    #[clap(
+        about = "Generate one-time recovery codes",
+        long_about = "Use this command to generate one-time recovery codes for your password store. Generating new codes invalidates the previous ones."
+    )]
+    Generate {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            default_value_t = 10,
+            help = "The number of recovery codes to generate."
+        )]
+        count: usize,
+    },
+
+    #[clap(
+        about = "Use a recovery code to set a new master password",
+        long_about = "Use this command to unlock the password store with a recovery code when you forgot the master password. You have to set a new master password, and the recovery code can't be used again."
+    )]
+    Use {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(short, long, help = "The recovery code to use.")]
+        code: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The new master password to be used to encrypt and decrypt the password store."
+        )]
+        new_master: Option<String>,
+    },
+}
+
+#[derive(Parser, Debug, PartialEq)]
+pub enum ShareKeyCommand {
+    #[clap(
+        about = "Split the password store key into shares",
+        long_about = "Use this command to split the key of your password store into shares. Any threshold of them unlock the password store, fewer reveal nothing about it. The shares are printed, or written to one file each with --output-dir."
+    )]
+    Split {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long,
+            help = "The master password used to decrypt the password store."
+        )]
+        master: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "The number of shares needed to unlock the password store."
+        )]
+        threshold: u8,
+        #[clap(short, long, help = "The number of shares to create.")]
+        shares: u8,
+        #[clap(
+            short,
+            long,
+            help = "Write each share to its own file in this directory."
+        )]
+        output_dir: Option<PathBuf>,
+    },
+
+    #[clap(
+        about = "Unlock the password store from shares",
+        long_about = "Use this command to unlock the password store from enough shares of its key. The passwords are listed, or a new master password is set with --reset-master. Shares not given with --share are prompted for."
+    )]
+    Combine {
+        #[clap(short, long, default_value_t=DEFAULT_PASSWORD_FILENAME.to_string(), help="The name of the password file to use. [default: passwords]")]
+        file_name: String,
+        #[clap(
+            short,
+            long = "share",
+            help = "A share of the password store key. Repeat for every share."
+        )]
+        shares: Vec<String>,
+        #[clap(short = 'r', long, default_value_t = false, aliases=&["show", "show-passwords", "reveal"], help="Whether to show the actual passwords or just the service and username information. [default: false]")]
+        show_passwords: bool,
+        #[clap(
+            long,
+            help = "Set a new master password instead of listing the passwords."
+        )]
+        reset_master: bool,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::NamedTempFile;
+
+    #[rstest(
+        input,
+        case(Some((Width(10), Height(10)))),
+        case(Some((Width(1000), Height(10)))),
+        case(Some((Width(10), Height(1000)))),
+        case(Some((Width(50), Height(20)))),
+        case(Some((Width(500), Height(200)))),
+        case(Some((Width(70), Height(40)))),
+        case(None),
+    )]
+    fn test_get_about(input: Option<(Width, Height)>) {
+        let received = get_about(input);
+        assert!(received.contains(ABOUT));
+        let ascii_art_lines = ASCII_ART_ABOUT.lines().collect::<Vec<&str>>();
+        let ascii_art_height = ascii_art_lines.len();
+        let ascii_art_width = ascii_art_lines
+            .iter()
+            .map(|line| line.len())
+            .max()
+            .unwrap_or(0);
+        if let Some((Width(w), Height(h))) = input {
             if w as usize >= ascii_art_width && h as usize >= ascii_art_height {
                 assert!(received.contains(ASCII_ART_ABOUT.lines().next().unwrap()))
             }
@@ -401,11 +1104,24 @@ mod test {
                 uppercase: true,
                 lowercase: true,
                 numbers: true,
+                urls: vec![],
+                notes: None,
+                tags: vec![],
+                overwrite: false,
+                keep_both: false,
+                fields: vec![],
+                secret_fields: vec![],
+                totp: None,
+                hotp: None,
+                secret_file: None,
+                kind: KindArgs::default(),
             },
+            keyfile: None,
+            cipher: None,
         }
     ),
     case(
-        &["lockbox", "add", "-s", "test_service", "-u", "test_username", "-p", "test_password"],
+        &["lockbox", "add", "-s", "test_service", "-u", "test_username", "-p", "test_password", "--url", "https://example.com", "--note", "work account", "--tag", "work", "--tag", "email", "--keep-both", "--field", "account=42=a", "--secret-field", "pin"],
         Args {
             command: Command::Add {
                 file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
@@ -419,7 +1135,56 @@ mod test {
                 uppercase: true,
                 lowercase: true,
                 numbers: true,
+                urls: vec!["https://example.com".to_string()],
+                notes: Some("work account".to_string()),
+                tags: vec!["work".to_string(), "email".to_string()],
+                overwrite: false,
+                keep_both: true,
+                fields: vec![("account".to_string(), "42=a".to_string())],
+                secret_fields: vec!["pin".to_string()],
+                totp: None,
+                hotp: None,
+                secret_file: None,
+                kind: KindArgs::default(),
             },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "add", "-s", "visa", "--kind", "card", "--cardholder", "Jane Doe", "--expiry", "04/27", "--secret-file", "card.txt"],
+        Args {
+            command: Command::Add {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: "visa".to_string(),
+                username: None,
+                password: None,
+                master: None,
+                generate: false,
+                length: Length::Sixteen,
+                symbols: false,
+                uppercase: true,
+                lowercase: true,
+                numbers: true,
+                urls: vec![],
+                notes: None,
+                tags: vec![],
+                overwrite: false,
+                keep_both: false,
+                fields: vec![],
+                secret_fields: vec![],
+                totp: None,
+                hotp: None,
+                secret_file: Some(PathBuf::from("card.txt")),
+                kind: KindArgs {
+                    kind: Kind::Card,
+                    cardholder: Some("Jane Doe".to_string()),
+                    expiry: Some(CardExpiry { month: 4, year: 2027 }),
+                    ..Default::default()
+                },
+            },
+            keyfile: None,
+            cipher: None,
         }
     ),
     case(
@@ -433,16 +1198,21 @@ mod test {
                 numbers: true,
                 count: 1,
             },
+            keyfile: None,
+            cipher: None,
         }
     ),
     case(
-        &["lockbox", "list", "--master", "master_password"],
+        &["lockbox", "list", "--master", "master_password", "--keyfile", "/media/usb/lockbox.key"],
         Args {
             command: Command::List {
                 file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
                 master: Some("master_password".to_string()),
                 show_passwords: false,
+                kind: None,
             },
+            keyfile: Some(PathBuf::from("/media/usb/lockbox.key")),
+            cipher: None,
         }
     ),
     case(
@@ -450,21 +1220,301 @@ mod test {
         Args {
             command: Command::Remove {
                 file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
-                service: "service".to_string(),
+                service: Some("service".to_string()),
                 username: None,
+                id: None,
                 master: None,
             },
+            keyfile: None,
+            cipher: None,
         }
     ),
     case(
-        &["lockbox", "show", "-s", "service"],
+        &["lockbox", "show", "-s", "service", "--reveal"],
         Args {
             command: Command::Show {
                 file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
-                service: "service".to_string(),
+                service: Some("service".to_string()),
+                username: None,
+                id: None,
+                master: None,
+                reveal: true,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "edit", "-s", "service", "-u", "user", "--password", "--new-service", "renamed", "--new-username", "", "--note", "moved to sso", "--field", "pin="],
+        Args {
+            command: Command::Edit {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: Some("service".to_string()),
+                username: Some("user".to_string()),
+                id: None,
+                master: None,
+                password: Some("".to_string()),
+                generate: false,
+                length: Length::Sixteen,
+                symbols: false,
+                new_service: Some("renamed".to_string()),
+                new_username: Some("".to_string()),
+                urls: vec![],
+                notes: Some("moved to sso".to_string()),
+                tags: vec![],
+                fields: vec![("pin".to_string(), "".to_string())],
+                secret_fields: vec![],
+                totp: None,
+                hotp: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "edit", "--id", "67e55044-10b1-426f-9247-bb680e5fe0c8", "-g", "-l", "32", "--totp", "JBSWY3DPEHPK3PXP"],
+        Args {
+            command: Command::Edit {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: None,
+                username: None,
+                id: Some(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap()),
+                master: None,
+                password: None,
+                generate: true,
+                length: Length::ThirtyTwo,
+                symbols: false,
+                new_service: None,
+                new_username: None,
+                urls: vec![],
+                notes: None,
+                tags: vec![],
+                fields: vec![],
+                secret_fields: vec![],
+                totp: Some("JBSWY3DPEHPK3PXP".parse().unwrap()),
+                hotp: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "history", "-s", "service", "--show-passwords"],
+        Args {
+            command: Command::History {
+                command: None,
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: Some("service".to_string()),
+                username: None,
+                id: None,
+                master: None,
+                show_passwords: true,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "history", "restore", "-s", "service", "-u", "user", "-n", "2"],
+        Args {
+            command: Command::History {
+                command: Some(HistoryCommand::Restore {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    service: Some("service".to_string()),
+                    username: Some("user".to_string()),
+                    id: None,
+                    master: None,
+                    number: 2,
+                }),
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: None,
                 username: None,
+                id: None,
                 master: None,
+                show_passwords: false,
             },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "copy", "--id", "67e55044-10b1-426f-9247-bb680e5fe0c8"],
+        Args {
+            command: Command::Copy {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: None,
+                username: None,
+                id: Some(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap()),
+                master: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "otp", "-s", "bank"],
+        Args {
+            command: Command::Otp {
+                command: None,
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: Some("bank".to_string()),
+                username: None,
+                id: None,
+                master: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "otp", "resync", "-s", "bank", "--code", "969429", "338314"],
+        Args {
+            command: Command::Otp {
+                command: Some(OtpCommand::Resync {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    service: Some("bank".to_string()),
+                    username: None,
+                    id: None,
+                    master: None,
+                    codes: vec!["969429".to_string(), "338314".to_string()],
+                }),
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: None,
+                username: None,
+                id: None,
+                master: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "totp", "-s", "github", "-u", "octocat"],
+        Args {
+            command: Command::Totp {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                service: Some("github".to_string()),
+                username: Some("octocat".to_string()),
+                id: None,
+                master: None,
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "slot", "add", "--new-keyfile", "/media/usb/lockbox.key", "--label", "usb"],
+        Args {
+            command: Command::Slot {
+                command: SlotCommand::Add {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    master: None,
+                    new_password: None,
+                    new_keyfile: Some(PathBuf::from("/media/usb/lockbox.key")),
+                    with_password: false,
+                    label: Some("usb".to_string()),
+                },
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "slot", "remove", "-i", "1"],
+        Args {
+            command: Command::Slot {
+                command: SlotCommand::Remove {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    master: None,
+                    index: 1,
+                },
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "recovery", "generate", "-c", "5"],
+        Args {
+            command: Command::Recovery {
+                command: RecoveryCommand::Generate {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    master: None,
+                    count: 5,
+                },
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "recovery", "use", "--code", "ABCDE-FGHJK-LMNPQ-RSTUV"],
+        Args {
+            command: Command::Recovery {
+                command: RecoveryCommand::Use {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    code: Some("ABCDE-FGHJK-LMNPQ-RSTUV".to_string()),
+                    new_master: None,
+                },
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "share-key", "split", "--threshold", "3", "--shares", "5", "--cipher", "xchacha20-poly1305"],
+        Args {
+            command: Command::ShareKey {
+                command: ShareKeyCommand::Split {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    master: None,
+                    threshold: 3,
+                    shares: 5,
+                    output_dir: None,
+                },
+            },
+            keyfile: None,
+            cipher: Some(Cipher::XChaCha20Poly1305),
+        }
+    ),
+    case(
+        &["lockbox", "share-key", "combine", "--share", "aa", "--share", "bb", "--reset-master"],
+        Args {
+            command: Command::ShareKey {
+                command: ShareKeyCommand::Combine {
+                    file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                    shares: vec!["aa".to_string(), "bb".to_string()],
+                    show_passwords: false,
+                    reset_master: true,
+                },
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "restore", "--backup", "2"],
+        Args {
+            command: Command::Restore {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                master: None,
+                backup: Some(2),
+            },
+            keyfile: None,
+            cipher: None,
+        }
+    ),
+    case(
+        &["lockbox", "kdf", "--target-ms", "500", "--apply"],
+        Args {
+            command: Command::Kdf {
+                file_name: DEFAULT_PASSWORD_FILENAME.to_string(),
+                master: None,
+                target_ms: 500,
+                apply: true,
+            },
+            keyfile: None,
+            cipher: None,
         }
     )
     )]
@@ -473,6 +1523,34 @@ mod test {
         assert_eq!(args, expected);
     }
 
+    #[test]
+    fn test_into_entry_kind() {
+        let kind = KindArgs {
+            kind: Kind::SshKey,
+            public_key: Some("ssh-ed25519 AAAA".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            kind.into_entry_kind(),
+            Ok(EntryKind::SshKey {
+                public_key: Some("ssh-ed25519 AAAA".to_string())
+            })
+        );
+        let kind = KindArgs {
+            kind: Kind::Note,
+            public_key: Some("ssh-ed25519 AAAA".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            kind.into_entry_kind(),
+            Err("--public-key can only be used with --kind ssh-key".to_string())
+        );
+        let args = [
+            "lockbox", "add", "-s", "visa", "--kind", "card", "--expiry", "13/27",
+        ];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+
     #[rstest(
         input,
         expected,
@@ -1,18 +1,24 @@
 use crate::{
     cli::{args::Length, io::read_hidden_input},
-    store::PasswordStore,
+    crypto::{benchmark_argon2, Kdf},
+    error::{LockboxError, Result},
+    pass::{EntryChanges, EntryDetails, EntryRef, Kind, OnDuplicate, PasswordEntry},
+    store::{
+        header::{SlotKind, SlotSecret},
+        PasswordStore,
+    },
 };
 use copypasta::{ClipboardContext, ClipboardProvider};
 use passwords::PasswordGenerator;
-use std::io::Write;
+use std::{fs, io::Write, path::Path, time::Duration};
 
-use super::io::{print, MessageType, PromptPassword};
+use super::io::{print, print_key_value_with_color, MessageType, PromptPassword};
 
-pub fn copy_to_clipboard(password: String) -> anyhow::Result<()> {
-    let mut ctx =
-        ClipboardContext::new().map_err(|_| anyhow::anyhow!("Unable to initialize clipboard"))?;
-    ctx.set_contents(password)
-        .map_err(|_| anyhow::anyhow!("Unable to set clipboard contents"))?;
+pub fn copy_to_clipboard(password: &str) -> Result<()> {
+    let mut ctx = ClipboardContext::new()
+        .map_err(|_| LockboxError::Clipboard("Unable to initialize clipboard".to_string()))?;
+    ctx.set_contents(password.to_string())
+        .map_err(|_| LockboxError::Clipboard("Unable to set clipboard contents".to_string()))?;
     Ok(())
 }
 
@@ -27,31 +33,103 @@ pub fn add_password<W: Write>(
     password: Option<String>,
     generate: bool,
     password_generator: PasswordGenerator,
-) -> anyhow::Result<()> {
+    details: EntryDetails,
+    on_duplicate: OnDuplicate,
+) -> Result<()> {
     password_store.load()?; // load to check if master password is correct before generating password
+    let exists = password_store
+        .find(service.clone(), username.clone())
+        .is_some();
+    if exists && on_duplicate == OnDuplicate::Fail {
+        password_store.release_lock();
+        return Err(LockboxError::DuplicateEntry { service, username });
+    }
     let password = if generate {
-        let password = password_generator
-            .generate_one()
-            .unwrap_or_else(|_| panic!("{}", "Failed to generate password"));
-        match copy_to_clipboard(password.clone()) {
-            Ok(_) => writeln!(writer, "Random password generated and copied to clipboard")?,
-            Err(err) => {
-                writeln!(writer, "Random password generated")?;
-                writeln!(
-                    writer,
-                    "Note: Failed to copy password to clipboard: {}",
-                    err
-                )?;
+        generate_and_copy(writer, &password_generator)?
+    } else {
+        match password {
+            Some(password) => password,
+            None => {
+                read_hidden_input(&details.kind.secret_label().to_lowercase(), prompt_password)?
             }
         }
-        password
-    } else {
-        password.unwrap_or_else(|| read_hidden_input("password", prompt_password))
     };
-    password_store.push(service, username, password)?.dump()?;
+    let entry = PasswordEntry::new(service.clone(), username, password).with_details(details);
+    let stored = password_store.push_entry(entry, on_duplicate)?;
+    if stored.service != service {
+        writeln!(writer, "Password stored as {}", stored.service)?;
+    } else if exists {
+        writeln!(writer, "Existing password replaced")?;
+    }
+    password_store.dump()?;
+    Ok(())
+}
+
+/// How `edit` changes the password of an entry.
+pub enum NewPassword {
+    Keep,
+    Set(String),
+    Prompt,
+    Generate(PasswordGenerator),
+}
+
+/// Changes an existing entry in place, so it keeps its id and created time.
+pub fn edit_password<W: Write>(
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+    new_password: NewPassword,
+    mut changes: EntryChanges,
+) -> Result<()> {
+    if password_store.load()?.get(&entry).is_none() {
+        password_store.release_lock();
+        return Err(LockboxError::EntryNotFound);
+    }
+    changes.password = match new_password {
+        NewPassword::Keep => None,
+        NewPassword::Set(password) => Some(password),
+        NewPassword::Prompt => Some(read_hidden_input("new password", prompt_password)?),
+        NewPassword::Generate(password_generator) => {
+            Some(generate_and_copy(writer, &password_generator)?)
+        }
+    };
+    if changes.is_empty() {
+        password_store.release_lock();
+        writeln!(writer, "Nothing to change")?;
+        return Ok(());
+    }
+    if let Err(err) = password_store.edit(&entry, changes) {
+        password_store.release_lock();
+        return Err(err);
+    }
+    password_store.dump()?;
+    print(writer, "Password updated", Some(MessageType::Success));
     Ok(())
 }
 
+/// Generates a password and copies it to the clipboard, noting when copying fails.
+fn generate_and_copy<W: Write>(
+    writer: &mut W,
+    password_generator: &PasswordGenerator,
+) -> Result<String> {
+    let password = password_generator
+        .generate_one()
+        .map_err(|err| LockboxError::PasswordGeneration(err.to_string()))?;
+    match copy_to_clipboard(&password) {
+        Ok(_) => writeln!(writer, "Random password generated and copied to clipboard")?,
+        Err(err) => {
+            writeln!(writer, "Random password generated")?;
+            writeln!(
+                writer,
+                "Note: Failed to copy password to clipboard: {}",
+                err
+            )?;
+        }
+    }
+    Ok(password)
+}
+
 pub fn generate_password<W: Write>(
     writer: &mut W,
     length: Length,
@@ -60,7 +138,7 @@ pub fn generate_password<W: Write>(
     lowercase: bool,
     numbers: bool,
     count: usize,
-) -> anyhow::Result<()> {
+) -> Result<()> {
     let password_generator = PasswordGenerator::new()
         .length(length.get_val())
         .lowercase_letters(lowercase)
@@ -86,7 +164,7 @@ pub fn generate_password<W: Write>(
         match password_generator.generate_one() {
             Ok(password) => {
                 print(writer, &password, Some(MessageType::Success));
-                match copy_to_clipboard(password) {
+                match copy_to_clipboard(&password) {
                   Ok(_) => print(
                         writer,
                         "(Random password generated. Copied to clipboard)",
@@ -110,25 +188,22 @@ pub fn generate_password<W: Write>(
 pub fn show_password<W: Write>(
     writer: &mut W,
     password_store: &mut PasswordStore,
-    service: String,
-    username: Option<String>,
-) -> anyhow::Result<()> {
-    let password = password_store.load()?.find(service, username);
-    if let Some(password) = password {
-        password.print_password(writer, Some(MessageType::Info));
-        match password.copy_password() {
-            Ok(_) => print(writer, "(Password copied to clipboard)", None),
-            Err(err) => print(
-                writer,
-                &format!(
-                    "(Random password generated. Failed to copy password to clipboard: {err})"
-                ),
-                Some(MessageType::Warning),
-            ),
-        }
-    } else {
-        writeln!(writer, "Password not found")?;
+    entry: EntryRef,
+    reveal: bool,
+) -> Result<()> {
+    let mut store = password_store.load_for_reading()?;
+    let password = store.get(&entry).ok_or(LockboxError::EntryNotFound)?;
+    password.print_password(writer, Some(MessageType::Info));
+    password.print_details(writer, reveal, Some(MessageType::Info));
+    match password.copy_password() {
+        Ok(_) => print(writer, "(Password copied to clipboard)", None),
+        Err(err) => print(
+            writer,
+            &format!("(Random password generated. Failed to copy password to clipboard: {err})"),
+            Some(MessageType::Warning),
+        ),
     }
+    store.mark_used(&entry);
     Ok(())
 }
 
@@ -136,23 +211,53 @@ pub fn list_passwords<W: Write>(
     writer: &mut W,
     password_store: &mut PasswordStore,
     show_passwords: bool,
-) -> anyhow::Result<()> {
+    kind: Option<Kind>,
+) -> Result<()> {
     password_store
-        .load()?
-        .print(writer, show_passwords, Some(MessageType::Info));
+        .load_for_reading()?
+        .print(writer, show_passwords, kind, Some(MessageType::Info));
     Ok(())
 }
 
 pub fn remove_password<W: Write>(
     writer: &mut W,
     password_store: &mut PasswordStore,
-    service: String,
-    username: Option<String>,
-) -> anyhow::Result<()> {
+    entry: EntryRef,
+) -> Result<()> {
+    password_store.load()?.pop(writer, &entry).dump()?;
+    Ok(())
+}
+
+pub fn show_history<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+    show_passwords: bool,
+) -> Result<()> {
     password_store
-        .load()?
-        .pop(writer, service, username)
-        .dump()?;
+        .load_for_reading()?
+        .get(&entry)
+        .ok_or(LockboxError::EntryNotFound)?
+        .print_history(writer, show_passwords, Some(MessageType::Info));
+    Ok(())
+}
+
+pub fn restore_password<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+    number: usize,
+) -> Result<()> {
+    if let Err(err) = password_store.load()?.restore_password(&entry, number) {
+        password_store.release_lock();
+        return Err(err);
+    }
+    password_store.dump()?;
+    print(
+        writer,
+        &format!("Previous password {number} restored"),
+        Some(MessageType::Success),
+    );
     Ok(())
 }
 
@@ -160,7 +265,7 @@ pub fn update_master_password<W: Write>(
     writer: &mut W,
     new_master_password: String,
     password_store: &mut PasswordStore,
-) -> anyhow::Result<()> {
+) -> Result<()> {
     password_store
         .load()?
         .update_master(new_master_password)
@@ -176,30 +281,400 @@ pub fn update_master_password<W: Write>(
 pub fn copy_password<W: Write>(
     writer: &mut W,
     password_store: &mut PasswordStore,
-    service: String,
-    username: Option<String>,
-) -> anyhow::Result<()> {
-    let password = password_store.load()?.find(service, username);
-    if let Some(password) = password {
-        match password.copy_password() {
-            Ok(_) => print(writer, "(Password copied to clipboard)", None),
-            Err(err) => print(
-                writer,
-                &format!(
-                    "(Random password generated. Failed to copy password to clipboard: {err})"
-                ),
-                Some(MessageType::Warning),
-            ),
+    entry: EntryRef,
+) -> Result<()> {
+    let mut store = password_store.load_for_reading()?;
+    let password = store.get(&entry).ok_or(LockboxError::EntryNotFound)?;
+    match password.copy_password() {
+        Ok(_) => print(writer, "(Password copied to clipboard)", None),
+        Err(err) => print(
+            writer,
+            &format!("(Random password generated. Failed to copy password to clipboard: {err})"),
+            Some(MessageType::Warning),
+        ),
+    }
+    store.mark_used(&entry);
+    Ok(())
+}
+
+/// Prints the current TOTP code of an entry and copies it, with the seconds it stays valid.
+pub fn show_totp<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+) -> Result<()> {
+    let mut store = password_store.load_for_reading()?;
+    let totp = store
+        .get(&entry)
+        .ok_or(LockboxError::EntryNotFound)?
+        .totp
+        .as_ref()
+        .ok_or(LockboxError::NoTotp)?;
+    let (code, remaining) = totp.code()?;
+    print_key_value_with_color(writer, "Code", &code, None, Some(MessageType::Info), None);
+    print(
+        writer,
+        &format!("(Valid for {remaining} more seconds)"),
+        None,
+    );
+    match copy_to_clipboard(&code) {
+        Ok(_) => print(writer, "(Code copied to clipboard)", None),
+        Err(err) => print(
+            writer,
+            &format!("(Failed to copy code to clipboard: {err})"),
+            Some(MessageType::Warning),
+        ),
+    }
+    store.mark_used(&entry);
+    Ok(())
+}
+
+/// Prints the next HOTP code of an entry and copies it. The advanced counter is written before
+/// the code is shown, so a code is never given twice.
+pub fn show_hotp<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+) -> Result<()> {
+    let code = match password_store.load()?.next_hotp_code(&entry) {
+        Ok(code) => code,
+        Err(err) => {
+            password_store.release_lock();
+            return Err(err);
+        }
+    };
+    password_store.dump()?;
+    print_key_value_with_color(writer, "Code", &code, None, Some(MessageType::Info), None);
+    match copy_to_clipboard(&code) {
+        Ok(_) => print(writer, "(Code copied to clipboard)", None),
+        Err(err) => print(
+            writer,
+            &format!("(Failed to copy code to clipboard: {err})"),
+            Some(MessageType::Warning),
+        ),
+    }
+    Ok(())
+}
+
+pub fn resync_hotp<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    entry: EntryRef,
+    codes: Vec<String>,
+) -> Result<()> {
+    let counter = match password_store.load()?.resync_hotp(&entry, &codes) {
+        Ok(counter) => counter,
+        Err(err) => {
+            password_store.release_lock();
+            return Err(err);
         }
+    };
+    password_store.dump()?;
+    print(
+        writer,
+        &format!("HOTP counter resynced, the next code is for counter {counter}"),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+pub fn benchmark_kdf<W: Write>(writer: &mut W, target: Duration) -> Result<Kdf> {
+    print(
+        writer,
+        &format!(
+            "Benchmarking Argon2id for a target of {}ms...",
+            target.as_millis()
+        ),
+        None,
+    );
+    let (kdf, estimate) = benchmark_argon2(target)?;
+    print(writer, &kdf.to_string(), Some(MessageType::Success));
+    print(
+        writer,
+        &format!("(Estimated unlock time: {}ms)", estimate.as_millis()),
+        None,
+    );
+    Ok(kdf)
+}
+
+pub fn update_kdf<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    kdf: Kdf,
+) -> Result<()> {
+    password_store.load()?.update_kdf(kdf)?.dump()?;
+    print(
+        writer,
+        "Password store re-encrypted with the new key derivation parameters",
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+pub fn add_key_slot<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    label: String,
+    secret: SlotSecret,
+) -> Result<()> {
+    password_store.load()?.add_slot(label, secret)?.dump()?;
+    print(
+        writer,
+        "Key slot added successfully",
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+pub fn list_key_slots<W: Write>(writer: &mut W, password_store: &mut PasswordStore) -> Result<()> {
+    password_store.load()?;
+    for (index, slot) in password_store.slots().iter().enumerate() {
+        print_key_value_with_color(
+            writer,
+            "Slot",
+            &index.to_string(),
+            None,
+            Some(MessageType::Info),
+            Some(", "),
+        );
+        print_key_value_with_color(
+            writer,
+            "Kind",
+            &slot.kind.to_string(),
+            None,
+            Some(MessageType::Info),
+            Some(", "),
+        );
+        print_key_value_with_color(
+            writer,
+            "Label",
+            &slot.label,
+            None,
+            Some(MessageType::Info),
+            Some(", "),
+        );
+        let end = if password_store.unlocked_slot() == Some(index) {
+            " (in use)\n"
+        } else {
+            "\n"
+        };
+        print_key_value_with_color(
+            writer,
+            "KDF",
+            &slot.kdf.to_string(),
+            None,
+            Some(MessageType::Info),
+            Some(end),
+        );
+    }
+    Ok(())
+}
+
+pub fn remove_key_slot<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    index: usize,
+) -> Result<()> {
+    password_store.load()?.remove_slot(index)?.dump()?;
+    print(writer, "Key slot removed", Some(MessageType::Success));
+    Ok(())
+}
+
+pub fn generate_recovery_codes<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    count: usize,
+) -> Result<()> {
+    let codes = password_store.load()?.generate_recovery_codes(count)?;
+    password_store.dump()?;
+    writeln!(writer)?;
+    for code in codes {
+        print(writer, &code, Some(MessageType::Success));
+    }
+    print(
+        writer,
+        "Write these recovery codes down and keep them somewhere safe. Each code unlocks the password store once, after which you have to set a new master password. Any previous recovery codes no longer work.",
+        Some(MessageType::Warning),
+    );
+    Ok(())
+}
+
+pub fn use_recovery_code<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    recovery_code: &str,
+    new_master: String,
+) -> Result<()> {
+    password_store.recover(recovery_code, new_master)?.dump()?;
+    let codes_left = password_store
+        .slots()
+        .iter()
+        .filter(|slot| slot.kind == SlotKind::Recovery)
+        .count();
+    print(
+        writer,
+        &format!(
+            "Master password updated successfully. The recovery code has been used up, {codes_left} recovery codes left."
+        ),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+pub fn split_key<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    threshold: u8,
+    count: u8,
+    output_dir: Option<&Path>,
+) -> Result<()> {
+    password_store.load()?;
+    // A store migrated on load has a new data key, which has to be saved before it's split
+    if password_store.has_unsaved_changes() {
+        password_store.dump()?;
     } else {
-        writeln!(writer, "Password not found")?;
+        password_store.release_lock();
+    }
+    let shares = password_store.split_data_key(threshold, count)?;
+    writeln!(writer)?;
+    match output_dir {
+        Some(output_dir) => {
+            fs::create_dir_all(output_dir)?;
+            for (index, share) in shares.iter().enumerate() {
+                let share_path = output_dir.join(format!("share-{}.txt", index + 1));
+                fs::write(&share_path, format!("{share}\n"))?;
+                print(
+                    writer,
+                    &format!("Share {} written to {}", index + 1, share_path.display()),
+                    Some(MessageType::Success),
+                );
+            }
+        }
+        None => {
+            for (index, share) in shares.iter().enumerate() {
+                print_key_value_with_color(
+                    writer,
+                    &format!("Share {}", index + 1),
+                    share,
+                    None,
+                    Some(MessageType::Success),
+                    None,
+                );
+            }
+        }
+    }
+    print(
+        writer,
+        &format!(
+            "Give each share to a different person. Any {threshold} of the {count} shares unlock the password store with `lockbox share-key combine`."
+        ),
+        Some(MessageType::Warning),
+    );
+    Ok(())
+}
+
+pub fn combine_key_shares<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    shares: &[String],
+    show_passwords: bool,
+    new_master: Option<String>,
+) -> Result<()> {
+    password_store.load_from_shares(shares)?;
+    match new_master {
+        Some(new_master) => {
+            password_store.reset_master(new_master)?.dump()?;
+            print(
+                writer,
+                "Master password updated successfully",
+                Some(MessageType::Success),
+            );
+        }
+        None => password_store.print(writer, show_passwords, None, Some(MessageType::Info)),
+    }
+    Ok(())
+}
+
+pub fn list_backups<W: Write>(writer: &mut W, password_store: &PasswordStore) -> Result<()> {
+    let backups = password_store.backups();
+    if backups.is_empty() {
+        print(writer, "No backups found", Some(MessageType::Warning));
+        return Ok(());
     }
+    writeln!(writer)?;
+    for (number, path) in backups {
+        let metadata = fs::metadata(&path)?;
+        let age = metadata
+            .modified()?
+            .elapsed()
+            .map(format_age)
+            .unwrap_or_else(|_| "just now".to_string());
+        print_key_value_with_color(
+            writer,
+            "Backup",
+            &number.to_string(),
+            None,
+            Some(MessageType::Info),
+            Some(", "),
+        );
+        print_key_value_with_color(
+            writer,
+            "Modified",
+            &age,
+            None,
+            Some(MessageType::Info),
+            Some(", "),
+        );
+        print_key_value_with_color(
+            writer,
+            "Size",
+            &format!("{} bytes", metadata.len()),
+            None,
+            Some(MessageType::Info),
+            None,
+        );
+    }
+    print(
+        writer,
+        "Run this command again with --backup <number> to roll back to a backup",
+        None,
+    );
     Ok(())
 }
 
+pub fn restore_backup<W: Write>(
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+    number: usize,
+) -> Result<()> {
+    password_store.restore_backup(number)?;
+    print(
+        writer,
+        &format!(
+            "Password store restored from backup {number}. The previous version is now backup 1."
+        ),
+        Some(MessageType::Success),
+    );
+    Ok(())
+}
+
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} minutes ago", seconds / 60),
+        3600..=86399 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{cli::io::MockPromptPassword, pass::PasswordEntry};
+    use crate::{
+        cli::io::MockPromptPassword,
+        pass::{CustomField, OnDuplicate, PasswordEntry},
+        store::lock::StoreLock,
+    };
 
     use super::*;
     use passwords::PasswordGenerator;
@@ -227,7 +702,7 @@ mod test {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, master, None).unwrap();
         let mock_prompt_password = &MockPromptPassword::new();
         let result = add_password(
             &mut writer,
@@ -238,11 +713,225 @@ mod test {
             password.map(|s| s.to_string()),
             generate,
             password_generator,
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         );
         assert!(result.is_ok());
         assert!(password_store.find(service, username).is_some());
     }
 
+    fn password_of(entry: &PasswordEntry) -> String {
+        let mut output = Vec::new();
+        entry.print_password(&mut output, None);
+        String::from_utf8(output)
+            .unwrap()
+            .trim_start_matches("Password: ")
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn test_add_password_duplicate() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+        let mut add = |password: &str, on_duplicate: OnDuplicate| {
+            let mut output = Vec::new();
+            let result = add_password(
+                &mut output,
+                &MockPromptPassword::new(),
+                &mut password_store,
+                "service".to_string(),
+                Some("username".to_string()),
+                Some(password.to_string()),
+                false,
+                PasswordGenerator::default(),
+                EntryDetails::default(),
+                on_duplicate,
+            );
+            (result, String::from_utf8(output).unwrap())
+        };
+        add("password1", OnDuplicate::Fail).0.unwrap();
+
+        let (result, _) = add("password2", OnDuplicate::Fail);
+        assert!(matches!(result, Err(LockboxError::DuplicateEntry { .. })));
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+
+        let (result, output) = add("password2", OnDuplicate::Overwrite);
+        result.unwrap();
+        assert!(output.contains("Existing password replaced"), "{output}");
+        let (result, output) = add("password3", OnDuplicate::KeepBoth);
+        result.unwrap();
+        assert!(
+            output.contains("Password stored as service (2)"),
+            "{output}"
+        );
+
+        let username = Some("username".to_string());
+        let original = password_store
+            .find("service".to_string(), username.clone())
+            .unwrap();
+        assert_eq!(password_of(original), "password2");
+        assert_eq!(original.history().count(), 1);
+        let second = password_store
+            .find("service (2)".to_string(), username)
+            .unwrap();
+        assert_ne!(second.id, original.id);
+    }
+
+    #[test]
+    fn test_edit_password() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push(
+                "service1".to_string(),
+                Some("username1".to_string()),
+                "password1".to_string(),
+            )
+            .unwrap()
+            .push("service2".to_string(), None, "password2".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        let id = password_store
+            .find("service1".to_string(), Some("username1".to_string()))
+            .unwrap()
+            .id
+            .unwrap();
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .returning(|_| Ok("typed_password".to_string()));
+
+        let mut output = Vec::new();
+        edit_password(
+            &mut output,
+            &mock_prompt_password,
+            &mut password_store,
+            EntryRef::name("service1".to_string(), Some("username1".to_string())),
+            NewPassword::Prompt,
+            EntryChanges {
+                password: None,
+                service: Some("renamed".to_string()),
+                username: Some("".to_string()),
+                urls: None,
+                notes: Some("moved to sso".to_string()),
+                tags: None,
+                fields: Vec::new(),
+                totp: None,
+                hotp: None,
+            },
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Password updated"));
+        let edited = password_store.get(&EntryRef::Id(id)).unwrap();
+        assert_eq!(
+            (edited.service.as_str(), edited.username.as_deref()),
+            ("renamed", None)
+        );
+        assert_eq!(edited.notes.as_deref(), Some("moved to sso"));
+        assert_eq!(password_of(edited), "typed_password");
+        assert_eq!(edited.history().count(), 1);
+
+        let mut rename = EntryChanges::default();
+        rename.service = Some("service2".to_string());
+        let result = edit_password(
+            &mut Vec::new(),
+            &mock_prompt_password,
+            &mut password_store,
+            EntryRef::Id(id),
+            NewPassword::Keep,
+            rename,
+        );
+        assert!(matches!(result, Err(LockboxError::DuplicateEntry { .. })));
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+
+        let result = edit_password(
+            &mut Vec::new(),
+            &mock_prompt_password,
+            &mut password_store,
+            EntryRef::name("service1".to_string(), Some("username1".to_string())),
+            NewPassword::Set("password".to_string()),
+            EntryChanges::default(),
+        );
+        assert!(matches!(result, Err(LockboxError::EntryNotFound)));
+
+        let mut output = Vec::new();
+        edit_password(
+            &mut output,
+            &mock_prompt_password,
+            &mut password_store,
+            EntryRef::Id(id),
+            NewPassword::Keep,
+            EntryChanges::default(),
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Nothing to change"));
+    }
+
+    #[test]
+    fn test_password_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password1".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        let entry = EntryRef::name("service".to_string(), None);
+
+        let mut output = Vec::new();
+        show_history(&mut output, &mut password_store, entry.clone(), false).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("No previous passwords"));
+
+        edit_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            entry.clone(),
+            NewPassword::Set("password2".to_string()),
+            EntryChanges::default(),
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        show_history(&mut output, &mut password_store, entry.clone(), true).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("password1"), "{output_str}");
+        assert!(!output_str.contains("password2"), "{output_str}");
+
+        let result = restore_password(&mut Vec::new(), &mut password_store, entry.clone(), 2);
+        assert!(matches!(result, Err(LockboxError::HistoryNotFound(2))));
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+
+        let mut output = Vec::new();
+        restore_password(&mut output, &mut password_store, entry.clone(), 1).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Previous password 1 restored"));
+        let mut output = Vec::new();
+        show_password(&mut output, &mut password_store, entry.clone(), false).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("password1"));
+        let mut output = Vec::new();
+        show_history(&mut output, &mut password_store, entry, true).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("password2"));
+    }
+
     #[rstest]
     #[case(Length::Eight, true, true, true, true, 2)]
     #[case(Length::Sixteen, false, true, true, true, 2)]
@@ -306,7 +995,7 @@ mod test {
     ) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::new(temp_file.clone(), master, None).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -319,30 +1008,242 @@ mod test {
             Some(password.clone()),
             false,
             PasswordGenerator::default(),
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         )
         .unwrap();
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let result = if expect_password_found {
-            show_password(&mut writer, &mut password_store, service, username)
+            show_password(
+                &mut writer,
+                &mut password_store,
+                EntryRef::name(service, username),
+                false,
+            )
         } else {
             show_password(
                 &mut writer,
                 &mut password_store,
-                "not_found_service".to_string(),
-                Some("not_found_username".to_string()),
+                EntryRef::name(
+                    "not_found_service".to_string(),
+                    Some("not_found_username".to_string()),
+                ),
+                false,
             )
         };
-        assert!(result.is_ok());
-
         output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
         if expect_password_found {
+            assert!(result.is_ok());
             assert!(output_str.contains(&password));
         } else {
-            assert!(output_str.contains("Password not found"));
+            assert!(matches!(result, Err(LockboxError::EntryNotFound)));
+        }
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_show_totp() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::new(temp_file, "master_password".to_string(), None).unwrap();
+        for (service, totp) in [
+            (
+                "github",
+                Some("otpauth://totp/GitHub:octocat?secret=JBSWY3DPEHPK3PXP&digits=8"),
+            ),
+            ("gitlab", None),
+        ] {
+            add_password(
+                &mut Vec::new(),
+                &MockPromptPassword::new(),
+                &mut password_store,
+                service.to_string(),
+                None,
+                Some("password".to_string()),
+                false,
+                PasswordGenerator::default(),
+                EntryDetails {
+                    totp: totp.map(|totp| totp.parse().unwrap()),
+                    ..Default::default()
+                },
+                OnDuplicate::Fail,
+            )
+            .unwrap();
+        }
+
+        let current_code = |password_store: &PasswordStore| {
+            let entry = password_store.find("github".to_string(), None).unwrap();
+            entry.totp.as_ref().unwrap().code().unwrap().0
+        };
+        let mut output = Vec::new();
+        let before = current_code(&password_store);
+        show_totp(
+            &mut output,
+            &mut password_store,
+            EntryRef::name("github".to_string(), None),
+        )
+        .unwrap();
+        let after = current_code(&password_store);
+        let output_str = String::from_utf8(output).unwrap();
+        // The period can roll over during the call
+        assert!(output_str.contains(&before) || output_str.contains(&after));
+        assert_eq!(before.len(), 8);
+        assert!(output_str.contains("more seconds"));
+        assert!(password_store
+            .find("github".to_string(), None)
+            .unwrap()
+            .last_used
+            .is_some());
+
+        assert!(matches!(
+            show_totp(
+                &mut Vec::new(),
+                &mut password_store,
+                EntryRef::name("gitlab".to_string(), None),
+            ),
+            Err(LockboxError::NoTotp)
+        ));
+    }
+
+    #[test]
+    fn test_hotp() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "bank".to_string(),
+            None,
+            Some("password".to_string()),
+            false,
+            PasswordGenerator::default(),
+            EntryDetails {
+                // The RFC 4226 test secret, whose codes are listed in its appendix D
+                hotp: Some("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".parse().unwrap()),
+                ..Default::default()
+            },
+            OnDuplicate::Fail,
+        )
+        .unwrap();
+        let entry = EntryRef::name("bank".to_string(), None);
+        let stored_counter = || {
+            let mut password_store =
+                PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+            password_store.load().unwrap();
+            let counter = password_store
+                .get(&entry)
+                .and_then(|entry| entry.hotp.as_ref())
+                .map(|hotp| hotp.counter);
+            password_store.release_lock();
+            counter
+        };
+
+        for expected in ["755224", "287082"] {
+            let mut output = Vec::new();
+            show_hotp(&mut output, &mut password_store, entry.clone()).unwrap();
+            assert!(String::from_utf8(output).unwrap().contains(expected));
         }
+        assert_eq!(stored_counter(), Some(2));
+
+        let codes = vec!["338314".to_string(), "254676".to_string()];
+        let mut output = Vec::new();
+        resync_hotp(&mut output, &mut password_store, entry.clone(), codes).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("the next code is for counter 6"));
+        assert_eq!(stored_counter(), Some(6));
+        let mut output = Vec::new();
+        show_hotp(&mut output, &mut password_store, entry.clone()).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("287922"));
+
+        let result = resync_hotp(
+            &mut Vec::new(),
+            &mut password_store,
+            entry.clone(),
+            vec!["000000".to_string(), "000000".to_string()],
+        );
+        assert!(matches!(result, Err(LockboxError::HotpResync(_))));
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+        assert_eq!(stored_counter(), Some(7));
+
+        let result = show_hotp(
+            &mut Vec::new(),
+            &mut password_store,
+            EntryRef::name("missing".to_string(), None),
+        );
+        assert!(matches!(result, Err(LockboxError::EntryNotFound)));
+    }
+
+    #[test]
+    fn test_show_password_details() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master_password".to_string(), None).unwrap();
+        add_password(
+            &mut Vec::new(),
+            &MockPromptPassword::new(),
+            &mut password_store,
+            "service".to_string(),
+            None,
+            Some("password".to_string()),
+            false,
+            PasswordGenerator::default(),
+            EntryDetails {
+                urls: vec!["https://example.com".to_string()],
+                notes: Some("recovery email is on file".to_string()),
+                tags: vec!["work".to_string()],
+                fields: vec![CustomField::new(
+                    "pin".to_string(),
+                    "0000".to_string(),
+                    true,
+                )],
+                ..Default::default()
+            },
+            OnDuplicate::Fail,
+        )
+        .unwrap();
+        let backups = password_store.backups();
+
+        let mut output = Vec::new();
+        show_password(
+            &mut output,
+            &mut password_store,
+            EntryRef::name("service".to_string(), None),
+            false,
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        for expected in [
+            "pin",
+            "***",
+            "URL",
+            "https://example.com",
+            "Notes",
+            "recovery email is on file",
+            "Tags",
+            "work",
+            "Created",
+        ] {
+            assert!(output_str.contains(expected), "{output_str}");
+        }
+        assert!(!output_str.contains("Last used"));
+        assert!(!output_str.contains("0000"));
+
+        // Recording the use rewrites the store without rotating the backups
+        assert_eq!(password_store.backups(), backups);
+        let entry = password_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None);
+        assert!(entry.unwrap().last_used.is_some());
+        password_store.release_lock();
     }
 
     #[rstest(
@@ -359,7 +1260,7 @@ mod test {
     fn test_list_passwords(show_passwords: bool, passwords: Vec<(&str, &str, &str)>) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, master, None).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -374,13 +1275,15 @@ mod test {
                 Some(password.to_string()),
                 false,
                 PasswordGenerator::default(),
+                EntryDetails::default(),
+                OnDuplicate::Fail,
             )
             .unwrap();
         }
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        let result = list_passwords(&mut writer, &mut password_store, show_passwords);
+        let result = list_passwords(&mut writer, &mut password_store, show_passwords, None);
         assert!(result.is_ok());
 
         output = writer.into_inner();
@@ -437,7 +1340,7 @@ mod test {
     ) {
         let master = "master_password".to_string();
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, master).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, master, None).unwrap();
         let output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
         let mock_prompt_password = &MockPromptPassword::new();
@@ -452,6 +1355,8 @@ mod test {
                 Some(password.to_string()),
                 false,
                 PasswordGenerator::default(),
+                EntryDetails::default(),
+                OnDuplicate::Fail,
             )
             .unwrap();
         }
@@ -461,20 +1366,22 @@ mod test {
         let result = remove_password(
             &mut output,
             &mut password_store,
-            service.to_string(),
-            Some(username.to_string()),
+            EntryRef::name(service.to_string(), Some(username.to_string())),
         );
         assert!(result.is_ok());
 
         for (service, username, password) in expected_passwords.iter() {
-            assert_eq!(
-                password_store.find(service.to_string(), Some(username.to_string())),
-                Some(&PasswordEntry::new(
-                    service.to_string(),
-                    Some(username.to_string()),
-                    password.to_string()
-                ))
+            let found = password_store
+                .find(service.to_string(), Some(username.to_string()))
+                .unwrap();
+            let mut expected = PasswordEntry::new(
+                service.to_string(),
+                Some(username.to_string()),
+                password.to_string(),
             );
+            (expected.id, expected.created, expected.modified) =
+                (found.id, found.created, found.modified);
+            assert_eq!(found, &expected);
         }
     }
 
@@ -482,7 +1389,7 @@ mod test {
     fn test_update_master_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut output = Vec::new();
-        let mut password_store = PasswordStore::new(temp_file, "master".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "master".to_string(), None).unwrap();
         update_master_password(
             &mut output,
             "new_master_password".to_string(),
@@ -492,4 +1399,202 @@ mod test {
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Master password updated successfully"));
     }
+
+    #[test]
+    fn test_benchmark_kdf() {
+        let mut output = Vec::new();
+        let kdf = benchmark_kdf(&mut output, Duration::from_millis(1)).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(matches!(kdf, Kdf::Argon2id { .. }));
+        assert!(output_str.contains("Argon2id"));
+        assert!(output_str.contains("Estimated unlock time"));
+    }
+
+    #[test]
+    fn test_update_kdf() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut output = Vec::new();
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master".to_string(), None).unwrap();
+        let kdf = Kdf::Argon2id {
+            memory_kib: 8192,
+            iterations: 1,
+            parallelism: 1,
+        };
+        update_kdf(&mut output, &mut password_store, kdf).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("re-encrypted"));
+
+        let mut password_store = PasswordStore::new(temp_file, "master".to_string(), None).unwrap();
+        assert_eq!(password_store.load().unwrap().kdf(), Some(kdf));
+    }
+
+    #[test]
+    fn test_key_slots() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master".to_string(), None).unwrap();
+        let mut output = Vec::new();
+        add_key_slot(
+            &mut output,
+            &mut password_store,
+            "second".to_string(),
+            SlotSecret::Password(b"second_password"),
+        )
+        .unwrap();
+        list_key_slots(&mut output, &mut password_store).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Key slot added successfully"));
+        assert!(output_str.contains("master password"));
+        assert!(output_str.contains("second"));
+        assert!(output_str.contains("(in use)"));
+
+        let mut output = Vec::new();
+        remove_key_slot(&mut output, &mut password_store, 1).unwrap();
+        assert!(remove_key_slot(&mut output, &mut password_store, 0).is_err());
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Key slot removed"));
+        password_store.release_lock();
+        let mut second_store =
+            PasswordStore::new(temp_file, "second_password".to_string(), None).unwrap();
+        assert!(second_store.load().is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master".to_string(), None).unwrap();
+        let mut output = Vec::new();
+        generate_recovery_codes(&mut output, &mut password_store, 2).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("keep them somewhere safe"));
+        let codes = password_store.generate_recovery_codes(2).unwrap();
+        password_store.dump().unwrap();
+
+        let mut output = Vec::new();
+        let mut recovery_store =
+            PasswordStore::new(temp_file.clone(), String::new(), None).unwrap();
+        use_recovery_code(
+            &mut output,
+            &mut recovery_store,
+            &codes[0],
+            "new_master".to_string(),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("1 recovery codes left"));
+        assert!(use_recovery_code(
+            &mut Vec::new(),
+            &mut recovery_store,
+            &codes[0],
+            "other_master".to_string()
+        )
+        .is_err());
+        let mut password_store =
+            PasswordStore::new(temp_file, "new_master".to_string(), None).unwrap();
+        assert!(password_store.load().is_ok());
+    }
+
+    #[test]
+    fn test_split_and_combine_key() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store =
+            PasswordStore::new(temp_file.clone(), "master".to_string(), None).unwrap();
+        password_store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        let contents = fs::read(&temp_file).unwrap();
+        let mut output = Vec::new();
+        let shares_dir = temp_dir.path().join("shares");
+        split_key(&mut output, &mut password_store, 2, 3, Some(&shares_dir)).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Share 3 written to"));
+        // Only printing the shares doesn't write the store
+        assert_eq!(fs::read(&temp_file).unwrap(), contents);
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+        let shares = (1..=3)
+            .map(|index| {
+                fs::read_to_string(shares_dir.join(format!("share-{index}.txt")))
+                    .unwrap()
+                    .trim()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+
+        let mut output = Vec::new();
+        let mut shares_store = PasswordStore::new(temp_file.clone(), String::new(), None).unwrap();
+        combine_key_shares(&mut output, &mut shares_store, &shares[1..], true, None).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("service"));
+        assert!(
+            combine_key_shares(&mut Vec::new(), &mut shares_store, &shares[..1], true, None)
+                .is_err()
+        );
+
+        let mut output = Vec::new();
+        combine_key_shares(
+            &mut output,
+            &mut shares_store,
+            &shares[..2],
+            false,
+            Some("new_master".to_string()),
+        )
+        .unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Master password updated successfully"));
+        let mut password_store =
+            PasswordStore::new(temp_file, "new_master".to_string(), None).unwrap();
+        assert!(password_store.load().is_ok());
+    }
+
+    #[test]
+    fn test_list_and_restore_backups() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut password_store = PasswordStore::new(temp_file, "master".to_string(), None).unwrap();
+        let mut output = Vec::new();
+        list_backups(&mut output, &password_store).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("No backups found"));
+
+        password_store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        let mut output = Vec::new();
+        list_backups(&mut output, &password_store).unwrap();
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Backup"));
+        assert!(output_str.contains("just now"));
+
+        let mut output = Vec::new();
+        restore_backup(&mut output, &mut password_store, 1).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("restored from backup 1"));
+        assert!(password_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_none());
+    }
+
+    #[rstest]
+    #[case(0, "just now")]
+    #[case(120, "2 minutes ago")]
+    #[case(7200, "2 hours ago")]
+    #[case(172800, "2 days ago")]
+    fn test_format_age(#[case] seconds: u64, #[case] expected: &str) {
+        assert_eq!(format_age(Duration::from_secs(seconds)), expected);
+    }
 }
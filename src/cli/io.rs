@@ -1,3 +1,4 @@
+use crate::error::{LockboxError, Result};
 use crossterm::style::{style, Attribute, Color, Stylize};
 use std::io::{stdout, BufRead, Error, Write};
 
@@ -6,38 +7,41 @@ use mockall::automock;
 
 #[cfg_attr(test, automock)]
 pub trait PromptPassword {
-    fn prompt_password(&self, prompt: String) -> Result<String, std::io::Error>;
+    fn prompt_password(&self, prompt: String) -> std::result::Result<String, Error>;
 }
 
 pub struct RpasswordPromptPassword;
 
 impl PromptPassword for RpasswordPromptPassword {
-    fn prompt_password(&self, prompt: String) -> Result<String, Error> {
+    fn prompt_password(&self, prompt: String) -> std::result::Result<String, Error> {
         rpassword::prompt_password(prompt)
     }
 }
 
-pub fn read_hidden_input(prompt: &str, prompt_password: &dyn PromptPassword) -> String {
+pub fn read_hidden_input(prompt: &str, prompt_password: &dyn PromptPassword) -> Result<String> {
     let input = prompt_password
         .prompt_password(format!(
             "Please enter the {prompt}\n{}",
             colorize(">> ", MessageType::DarkYellow)
         ))
-        .unwrap_or_else(|_| panic!("Failed to read {}", prompt));
-    input.trim().to_string()
+        .map_err(|source| LockboxError::Input {
+            prompt: prompt.to_string(),
+            source,
+        })?;
+    Ok(input.trim().to_string())
 }
 
 pub fn read_hidden_input_with_confirmation<W: Write>(
     writer: &mut W,
     prompt: &str,
     prompt_password: &dyn PromptPassword,
-) -> String {
+) -> Result<String> {
     let mut second_prompt = prompt.to_string();
     second_prompt.push_str(" again");
     let second_prompt = second_prompt.as_str();
     loop {
-        let first_input = read_hidden_input(prompt, prompt_password);
-        let second_input = read_hidden_input(second_prompt, prompt_password);
+        let first_input = read_hidden_input(prompt, prompt_password)?;
+        let second_input = read_hidden_input(second_prompt, prompt_password)?;
         if first_input != second_input {
             print(
                 writer,
@@ -46,7 +50,7 @@ pub fn read_hidden_input_with_confirmation<W: Write>(
             );
             continue;
         }
-        return second_input;
+        return Ok(second_input);
     }
 }
 
@@ -54,16 +58,21 @@ pub fn read_terminal_input<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     prompt: Option<&str>,
-) -> String {
+) -> Result<String> {
     if let Some(prompt) = prompt {
-        writeln!(writer, "{}", prompt).unwrap();
+        writeln!(writer, "{}", prompt)?;
     }
     write!(writer, "{}", colorize(">> ", MessageType::DarkYellow))
         .unwrap_or_else(|_| print!("{}", colorize(">> ", MessageType::DarkYellow)));
-    stdout().flush().unwrap();
+    stdout().flush()?;
     let mut input = String::new();
-    reader.read_line(&mut input).unwrap();
-    input.trim().to_owned()
+    reader
+        .read_line(&mut input)
+        .map_err(|source| LockboxError::Input {
+            prompt: "input".to_string(),
+            source,
+        })?;
+    Ok(input.trim().to_owned())
 }
 
 #[derive(Clone, Copy)]
@@ -135,7 +144,7 @@ mod tests {
     fn test_read_terminal_input() {
         let mut input = b"test input\n" as &[u8];
         let mut output = Vec::new();
-        let result = read_terminal_input(&mut input, &mut output, Some("test prompt"));
+        let result = read_terminal_input(&mut input, &mut output, Some("test prompt")).unwrap();
         assert_eq!(result, "test input");
         assert_eq!(
             String::from_utf8(output).unwrap(),
@@ -156,11 +165,42 @@ mod tests {
             .times(1)
             .returning(|_| Ok("secret".to_string()));
 
-        let input = read_hidden_input("password", &mock_prompt_password);
+        let input = read_hidden_input("password", &mock_prompt_password).unwrap();
         assert_eq!(input, "secret");
     }
 
-    use std::io::Cursor;
+    #[test]
+    fn test_read_hidden_input_without_tty() {
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Err(Error::new(ErrorKind::NotFound, "No such device")));
+
+        let err = read_hidden_input("master password", &mock_prompt_password).unwrap_err();
+        assert!(
+            matches!(err, LockboxError::Input { ref prompt, .. } if prompt == "master password")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Failed to read master password: No such device"
+        );
+    }
+
+    #[test]
+    fn test_read_terminal_input_fails() {
+        struct FailingReader;
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(Error::other("input closed"))
+            }
+        }
+        let mut input = std::io::BufReader::new(FailingReader);
+        let err = read_terminal_input(&mut input, &mut Vec::new(), None).unwrap_err();
+        assert!(matches!(err, LockboxError::Input { .. }));
+    }
+
+    use std::io::{Cursor, ErrorKind};
 
     #[test]
     fn test_read_hidden_input_with_confirmation() {
@@ -177,7 +217,8 @@ mod tests {
             &mut Cursor::new(Vec::new()),
             "password",
             &mock_prompt_password,
-        );
+        )
+        .unwrap();
         assert_eq!(input, "secret")
     }
 
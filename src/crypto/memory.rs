@@ -0,0 +1,131 @@
+use std::{fmt::Debug, ops::Deref};
+use zeroize::Zeroize;
+
+/// A secret kept on the heap that is zeroized when dropped. Where the OS allows it, its pages
+/// are also locked into memory so it is never written to swap.
+///
+/// Locking is best effort: it silently does nothing when the memory lock limit is reached, and
+/// unlocking a page also unlocks any other secret that happens to share it.
+pub struct Secret<T: Zeroize + AsRef<[u8]>>(Box<T>);
+
+impl<T: Zeroize + AsRef<[u8]>> Secret<T> {
+    pub fn new(value: T) -> Self {
+        let secret = Self(Box::new(value));
+        lock_pages(secret.bytes());
+        secret
+    }
+
+    fn bytes(&self) -> &[u8] {
+        T::as_ref(&self.0)
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]>> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]> + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Self::new(T::clone(&self.0))
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]>> Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]>> Drop for Secret<T> {
+    fn drop(&mut self) {
+        let (ptr, len) = (self.bytes().as_ptr(), self.bytes().len());
+        self.0.zeroize();
+        unlock_pages(ptr, len);
+    }
+}
+
+/// Stops the process from writing core dumps, which would contain any secret in memory at the
+/// time of a crash. On Linux this also keeps other processes of the same user from attaching
+/// a debugger. Best effort, failures are ignored.
+pub fn disable_core_dumps() {
+    #[cfg(unix)]
+    {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid rlimit that outlives the call
+        unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) };
+        #[cfg(target_os = "linux")]
+        // SAFETY: PR_SET_DUMPABLE only takes an integer argument
+        unsafe {
+            libc::prctl(libc::PR_SET_DUMPABLE, 0)
+        };
+    }
+}
+
+fn lock_pages(bytes: &[u8]) {
+    #[cfg(unix)]
+    if !bytes.is_empty() {
+        // SAFETY: the range is a live allocation owned by the caller
+        unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) };
+    }
+    #[cfg(not(unix))]
+    let _ = bytes;
+}
+
+fn unlock_pages(ptr: *const u8, len: usize) {
+    #[cfg(unix)]
+    if len != 0 {
+        // SAFETY: munlock only changes page attributes, the range was locked by `lock_pages`
+        unsafe { libc::munlock(ptr.cast(), len) };
+    }
+    #[cfg(not(unix))]
+    let _ = (ptr, len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let secret = Secret::new(String::from("master password"));
+        assert_eq!(secret.as_str(), "master password");
+        assert_eq!(secret.clone().as_str(), "master password");
+        assert_eq!(format!("{secret:?}"), "Secret(***)");
+        let key = Secret::new([7u8; 32]);
+        assert_eq!(*key, [7u8; 32]);
+    }
+
+    #[test]
+    fn test_disable_core_dumps() {
+        // Core dumps can't be turned back on, so check in a child process instead of changing
+        // the limits of the process that runs all the other tests
+        const CHILD_ENV: &str = "LOCKBOX_TEST_CORE_DUMPS_CHILD";
+        if std::env::var_os(CHILD_ENV).is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "crypto::memory::tests::test_disable_core_dumps"])
+                .env(CHILD_ENV, "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+        disable_core_dumps();
+        #[cfg(unix)]
+        {
+            let mut limit = libc::rlimit {
+                rlim_cur: 1,
+                rlim_max: 1,
+            };
+            // SAFETY: `limit` is a valid rlimit that outlives the call
+            assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) }, 0);
+            assert_eq!(limit.rlim_cur, 0);
+        }
+    }
+}
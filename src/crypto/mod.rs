@@ -1,41 +1,489 @@
+pub mod memory;
+
 use aes_gcm::{
-    aead::{generic_array::GenericArray, Aead, OsRng},
-    AeadCore, Aes256Gcm, KeyInit,
+    aead::{generic_array::GenericArray, Aead, Payload},
+    Aes256Gcm, KeyInit,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
+use blahaj::{Share, Sharks};
+use chacha20poly1305::XChaCha20Poly1305;
 use ring::{
-    pbkdf2,
+    digest, pbkdf2,
     rand::{SecureRandom, SystemRandom},
 };
-use std::num::NonZeroU32;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+use uuid::{Builder, Uuid};
+use zeroize::Zeroizing;
+
+use crate::error::{LockboxError, Result};
+
+pub const SALT_LEN: usize = 16;
+pub const KEY_LEN: usize = 32;
+/// An encryption key that is wiped from memory when dropped.
+pub type Key = Zeroizing<[u8; KEY_LEN]>;
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const RECOVERY_CODE_GROUPS: usize = 4;
+const RECOVERY_CODE_GROUP_LEN: usize = 5;
+pub const ARGON2_MIN_MEMORY_KIB: u32 = Params::DEFAULT_M_COST;
+const ARGON2_BENCHMARK_MEMORY_KIB: u32 = 64 * 1024;
+
+/// Key derivation function used to turn the master password into an encryption key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Display for Kdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kdf::Pbkdf2 { iterations } => {
+                write!(f, "PBKDF2-HMAC-SHA256 (iterations: {iterations})")
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(
+                f,
+                "Argon2id (memory: {memory_kib} KiB, iterations: {iterations}, parallelism: {parallelism})"
+            ),
+        }
+    }
+}
+
+impl Kdf {
+    pub fn derive_key(&self, secret: &[u8], salt: &[u8]) -> Result<Key> {
+        match *self {
+            Kdf::Pbkdf2 { iterations } => {
+                let iterations = NonZeroU32::new(iterations).ok_or_else(|| {
+                    LockboxError::Kdf("Invalid PBKDF2 iteration count".to_string())
+                })?;
+                Ok(pbkdf2_derive_key(secret, salt, iterations))
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    Params::new(memory_kib, iterations, parallelism, Some(32)).map_err(|err| {
+                        LockboxError::Kdf(format!("Invalid Argon2 parameters: {err}"))
+                    })?;
+                let mut enc_key = Zeroizing::new([0u8; KEY_LEN]);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(secret, salt, enc_key.as_mut())
+                    .map_err(|err| {
+                        LockboxError::Kdf(format!("Failed to derive encryption key: {err}"))
+                    })?;
+                Ok(enc_key)
+            }
+        }
+    }
+}
+
+/// Picks Argon2id parameters for which deriving a key takes roughly `target` on this machine.
+///
+/// Memory is halved (down to the recommended minimum) while a single pass is slower than the
+/// target, then the number of passes is scaled up to fill the remaining time.
+pub fn benchmark_argon2(target: Duration) -> Result<(Kdf, Duration)> {
+    let salt = get_random_salt()?;
+    let mut memory_kib = ARGON2_BENCHMARK_MEMORY_KIB;
+    loop {
+        let kdf = Kdf::Argon2id {
+            memory_kib,
+            iterations: 1,
+            parallelism: Params::DEFAULT_P_COST,
+        };
+        let start = Instant::now();
+        kdf.derive_key(b"lockbox benchmark", &salt)?;
+        let elapsed = start.elapsed().max(Duration::from_micros(1));
+        if elapsed > target && memory_kib > ARGON2_MIN_MEMORY_KIB {
+            memory_kib = (memory_kib / 2).max(ARGON2_MIN_MEMORY_KIB);
+            continue;
+        }
+        let iterations = (target.as_secs_f64() / elapsed.as_secs_f64())
+            .floor()
+            .max(1.0) as u32;
+        let kdf = Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism: Params::DEFAULT_P_COST,
+        };
+        return Ok((kdf, elapsed * iterations));
+    }
+}
 
-pub fn get_random_salt() -> [u8; 16] {
-    let mut salt = [0u8; 16];
-    let r = SystemRandom::new();
-    r.fill(&mut salt).unwrap();
-    salt
+/// AEAD cipher used to encrypt the password store contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Cipher {
+    #[default]
+    #[value(name = "aes-256-gcm")]
+    Aes256Gcm,
+    /// 192-bit nonces make random nonce collisions a non-issue, and it is fast without AES-NI.
+    #[value(name = "xchacha20-poly1305")]
+    XChaCha20Poly1305,
 }
 
-pub fn derive_encryption_key(master_password: &str, salt: &[u8]) -> [u8; 32] {
-    let mut enc_key: [u8; 32] = [0u8; 32];
+impl Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cipher::Aes256Gcm => write!(f, "AES-256-GCM"),
+            Cipher::XChaCha20Poly1305 => write!(f, "XChaCha20-Poly1305"),
+        }
+    }
+}
+
+impl Cipher {
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            Cipher::Aes256Gcm => 12,
+            Cipher::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    pub fn generate_nonce(&self) -> Result<Vec<u8>> {
+        let mut nonce = vec![0u8; self.nonce_len()];
+        fill_random(&mut nonce)?;
+        Ok(nonce)
+    }
+}
+
+pub fn get_random_salt() -> Result<[u8; SALT_LEN]> {
+    let mut salt = [0u8; SALT_LEN];
+    fill_random(&mut salt)?;
+    Ok(salt)
+}
+
+pub fn generate_key() -> Result<Key> {
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    fill_random(key.as_mut())?;
+    Ok(key)
+}
+
+/// A random (version 4) UUID.
+pub fn generate_id() -> Result<Uuid> {
+    let mut bytes = [0u8; 16];
+    fill_random(&mut bytes)?;
+    Ok(Builder::from_random_bytes(bytes).into_uuid())
+}
+
+/// An id that is always the same for the same `name`, for entries that don't have one saved.
+pub fn derive_id(name: &[u8]) -> Uuid {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&sha256(name)[..16]);
+    Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+fn fill_random(bytes: &mut [u8]) -> Result<()> {
+    SystemRandom::new()
+        .fill(bytes)
+        .map_err(|_| LockboxError::Random)
+}
+
+/// Copies `bytes` into a key, or `None` if they aren't `KEY_LEN` bytes long.
+pub fn key_from_slice(bytes: &[u8]) -> Option<Key> {
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    (bytes.len() == KEY_LEN).then(|| {
+        key.copy_from_slice(bytes);
+        key
+    })
+}
+
+/// A random recovery code like `ABCDE-FGH23-...`, 100 bits of entropy spelled without the
+/// easily confused characters `0`, `1`, `I` and `O`.
+pub fn generate_recovery_code() -> Result<String> {
+    let mut bytes = [0u8; RECOVERY_CODE_GROUPS * RECOVERY_CODE_GROUP_LEN];
+    fill_random(&mut bytes)?;
+    let code = bytes
+        .chunks(RECOVERY_CODE_GROUP_LEN)
+        .map(|group| {
+            group
+                .iter()
+                .map(|byte| RECOVERY_CODE_ALPHABET[(byte % 32) as usize] as char)
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("-");
+    Ok(code)
+}
+
+/// Drops separators and whitespace and uppercases the code, so it can be typed loosely.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which recover it. Each share is
+/// hex encoded and starts with the threshold, so combining needs nothing but the shares.
+pub fn split_secret(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<String>> {
+    if threshold < 2 {
+        return Err(LockboxError::InvalidShares(
+            "The threshold must be at least 2".to_string(),
+        ));
+    }
+    if count < threshold {
+        return Err(LockboxError::InvalidShares(
+            "The number of shares must be at least the threshold".to_string(),
+        ));
+    }
+    let shares = Sharks(threshold)
+        .dealer(secret)
+        .take(count as usize)
+        .map(|share| {
+            let mut bytes = vec![threshold];
+            bytes.extend(Vec::<u8>::from(&share));
+            hex::encode(bytes)
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// The number of shares needed to recover the secret of `share`.
+pub fn share_threshold(share: &str) -> Result<u8> {
+    decode_share(share).map(|(threshold, _)| threshold)
+}
+
+pub fn combine_shares(shares: &[String]) -> Result<Zeroizing<Vec<u8>>> {
+    let decoded = shares
+        .iter()
+        .map(|share| decode_share(share))
+        .collect::<Result<Vec<(u8, Share)>>>()?;
+    let threshold = match decoded.first() {
+        Some((threshold, _)) => *threshold,
+        None => return Err(LockboxError::InvalidShares("No shares given".to_string())),
+    };
+    if decoded.iter().any(|(other, _)| *other != threshold) {
+        return Err(LockboxError::InvalidShares(
+            "The shares belong to different splits".to_string(),
+        ));
+    }
+    let distinct = decoded
+        .iter()
+        .map(|(_, share)| share.x.0)
+        .collect::<HashSet<u8>>()
+        .len();
+    if distinct < threshold as usize {
+        return Err(LockboxError::InvalidShares(format!(
+            "Not enough shares: {threshold} different shares are needed, got {distinct}"
+        )));
+    }
+    Sharks(threshold)
+        .recover(decoded.iter().map(|(_, share)| share))
+        .map(Zeroizing::new)
+        .map_err(|err| LockboxError::InvalidShares(format!("Failed to combine shares: {err}")))
+}
+
+fn decode_share(share: &str) -> Result<(u8, Share)> {
+    let bytes = hex::decode(share.trim()).map_err(|_| invalid_share())?;
+    match bytes.split_first() {
+        Some((&threshold, share)) if threshold >= 2 => {
+            let share = Share::try_from(share).map_err(|_| invalid_share())?;
+            Ok((threshold, share))
+        }
+        _ => Err(invalid_share()),
+    }
+}
+
+fn invalid_share() -> LockboxError {
+    LockboxError::InvalidShares("Invalid share".to_string())
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, data).as_ref());
+    hash
+}
+
+fn pbkdf2_derive_key(secret: &[u8], salt: &[u8], iterations: NonZeroU32) -> Key {
+    let mut enc_key = Zeroizing::new([0u8; KEY_LEN]);
     pbkdf2::derive(
         pbkdf2::PBKDF2_HMAC_SHA256,
-        NonZeroU32::new(100_000).unwrap(),
+        iterations,
         salt,
-        master_password.as_bytes(),
-        &mut enc_key,
+        secret,
+        enc_key.as_mut(),
     );
     enc_key
 }
 
-pub fn get_cipher(master_password: &str, salt: &[u8]) -> Aes256Gcm {
-    let enc_key = derive_encryption_key(master_password, salt);
-    let cipher = Aes256Gcm::new(GenericArray::from_slice(&enc_key));
-    cipher
+/// Encrypts `plain_text` and authenticates it together with `aad`, which is not encrypted.
+pub fn encrypt(
+    cipher: Cipher,
+    key: &[u8; KEY_LEN],
+    nonce: &[u8],
+    plain_text: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let payload = Payload {
+        msg: plain_text,
+        aad,
+    };
+    match cipher {
+        Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(key))
+            .encrypt(GenericArray::from_slice(nonce), payload)
+            .map_err(|e| LockboxError::Encryption(format!("{e:?}"))),
+        Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new(GenericArray::from_slice(key))
+            .encrypt(GenericArray::from_slice(nonce), payload)
+            .map_err(|e| LockboxError::Encryption(format!("{e:?}"))),
+    }
+}
+
+/// Fails if the key is wrong, or the cipher text or `aad` were modified. The plain text is
+/// wiped from memory when dropped.
+pub fn decrypt(
+    cipher: Cipher,
+    key: &[u8; KEY_LEN],
+    nonce: &[u8],
+    cipher_text: &[u8],
+    aad: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let payload = Payload {
+        msg: cipher_text,
+        aad,
+    };
+    match cipher {
+        Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(key))
+            .decrypt(GenericArray::from_slice(nonce), payload)
+            .map(Zeroizing::new)
+            .map_err(|_| LockboxError::Decryption),
+        Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new(GenericArray::from_slice(key))
+            .decrypt(GenericArray::from_slice(nonce), payload)
+            .map(Zeroizing::new)
+            .map_err(|_| LockboxError::Decryption),
+    }
 }
 
-pub fn encrypt_contents(contents: &str, master_password: &str, salt: &[u8]) -> (Vec<u8>, Vec<u8>) {
-    let cipher = get_cipher(master_password, salt);
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-    let encrypted_text = cipher.encrypt(&nonce, contents.as_ref());
-    (encrypted_text.unwrap(), nonce.to_vec())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        kdf,
+        case(Kdf::Pbkdf2 { iterations: 1000 }),
+        case(Kdf::Argon2id { memory_kib: 1024, iterations: 1, parallelism: 1 })
+    )]
+    fn test_derive_key(kdf: Kdf) {
+        let salt = get_random_salt().unwrap();
+        let key = kdf.derive_key(b"master", &salt).unwrap();
+        assert_eq!(key, kdf.derive_key(b"master", &salt).unwrap());
+        assert_ne!(key, kdf.derive_key(b"other master", &salt).unwrap());
+        assert_ne!(
+            key,
+            kdf.derive_key(b"master", &get_random_salt().unwrap())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derive_key_invalid_argon2_params() {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 1,
+            iterations: 0,
+            parallelism: 1,
+        };
+        assert!(kdf
+            .derive_key(b"master", &get_random_salt().unwrap())
+            .is_err());
+    }
+
+    #[rstest(cipher, case(Cipher::Aes256Gcm), case(Cipher::XChaCha20Poly1305))]
+    fn test_encrypt_decrypt(cipher: Cipher) {
+        let key = generate_key().unwrap();
+        let nonce = cipher.generate_nonce().unwrap();
+        assert_eq!(nonce.len(), cipher.nonce_len());
+        let cipher_text = encrypt(cipher, &key, &nonce, b"secret", b"header").unwrap();
+        assert_eq!(
+            decrypt(cipher, &key, &nonce, &cipher_text, b"header")
+                .unwrap()
+                .as_slice(),
+            b"secret"
+        );
+        assert!(decrypt(
+            cipher,
+            &generate_key().unwrap(),
+            &nonce,
+            &cipher_text,
+            b"header"
+        )
+        .is_err());
+        assert!(decrypt(cipher, &key, &nonce, &cipher_text, b"other header").is_err());
+        let mut tampered = cipher_text.clone();
+        tampered[0] ^= 1;
+        assert!(decrypt(cipher, &key, &nonce, &tampered, b"header").is_err());
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let code = generate_recovery_code().unwrap();
+        assert_eq!(code.len(), 23);
+        assert_eq!(code.matches('-').count(), 3);
+        assert_ne!(code, generate_recovery_code().unwrap());
+        let normalized = normalize_recovery_code(&code);
+        assert_eq!(normalized.len(), 20);
+        assert!(normalized
+            .bytes()
+            .all(|byte| RECOVERY_CODE_ALPHABET.contains(&byte)));
+        assert_eq!(
+            normalize_recovery_code(&code.to_lowercase().replace('-', " ")),
+            normalized
+        );
+    }
+
+    #[test]
+    fn test_split_and_combine_shares() {
+        let secret = generate_key().unwrap().to_vec();
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert_eq!(share_threshold(&shares[0]).unwrap(), 3);
+        assert_eq!(*combine_shares(&shares[..3]).unwrap(), secret);
+        assert_eq!(*combine_shares(&shares[2..]).unwrap(), secret);
+        assert!(combine_shares(&shares[..2]).is_err());
+        let duplicated = vec![shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(combine_shares(&duplicated).is_err());
+        let other_split = split_secret(&secret, 2, 2).unwrap();
+        let mixed = vec![shares[0].clone(), shares[1].clone(), other_split[0].clone()];
+        assert!(combine_shares(&mixed).is_err());
+        assert!(combine_shares(&["not a share".to_string()]).is_err());
+        assert!(split_secret(&secret, 1, 5).is_err());
+        assert!(split_secret(&secret, 3, 2).is_err());
+    }
+
+    #[test]
+    fn test_benchmark_argon2() {
+        let (kdf, _) = benchmark_argon2(Duration::from_millis(1)).unwrap();
+        assert_eq!(
+            kdf,
+            Kdf::Argon2id {
+                memory_kib: ARGON2_MIN_MEMORY_KIB,
+                iterations: 1,
+                parallelism: Params::DEFAULT_P_COST,
+            }
+        );
+    }
 }
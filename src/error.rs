@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+/// Everything that can go wrong in lockbox, so callers can tell a wrong master password from
+/// a corrupt store or an I/O error without matching on messages.
+#[derive(Debug, thiserror::Error)]
+pub enum LockboxError {
+    #[error("Master password incorrect. Please try again.")]
+    WrongPassword,
+    #[error("Keyfile required. This password store can only be opened with its keyfile.")]
+    KeyfileRequired,
+    #[error("Keyfile incorrect. Please use the keyfile the password store was set up with.")]
+    WrongKeyfile,
+    #[error("Failed to read keyfile {}: {reason}", .path.display())]
+    InvalidKeyfile { path: PathBuf, reason: String },
+    #[error("Recovery code incorrect or already used. Please try again.")]
+    WrongRecoveryCode,
+    #[error("This password store has no recovery codes")]
+    NoRecoveryCodes,
+    #[error("The shares don't belong to this password store")]
+    WrongShares,
+    /// Shares that can't be combined, or split parameters that don't make sense.
+    #[error("{0}")]
+    InvalidShares(String),
+    #[error("Password store file is corrupt: {0}")]
+    CorruptStore(String),
+    /// A key slot opened, but the header or the encrypted passwords don't authenticate.
+    #[error("Password store file tampered: its header or encrypted passwords were modified")]
+    Tampered,
+    #[error("Unsupported password store format version {0}. Please upgrade lockbox.")]
+    UnsupportedVersion(u8),
+    #[error("Password store is locked by {}", locked_by(.pid))]
+    Locked { pid: Option<u32> },
+    #[error("Password store was changed by another process since it was loaded. Nothing was written, load it again and retry.")]
+    ChangedOnDisk,
+    /// A method that needs the decrypted store was called before `load`.
+    #[error("Load passwords before {0}")]
+    NotLoaded(&'static str),
+    #[error("{0}")]
+    KeySlot(String),
+    #[error("{0}")]
+    Kdf(String),
+    #[error("Failed to encrypt passwords: {0}")]
+    Encryption(String),
+    #[error("Failed to generate random bytes: the system random number generator failed")]
+    Random,
+    /// The key is wrong, or the cipher text or associated data were modified.
+    #[error("Failed to decrypt")]
+    Decryption,
+    #[error("Backup {0} not found")]
+    BackupNotFound(usize),
+    #[error("Failed to open backup {number}: {source}")]
+    InvalidBackup {
+        number: usize,
+        source: Box<LockboxError>,
+    },
+    #[error("Password not found")]
+    EntryNotFound,
+    #[error("Previous password {0} not found")]
+    HistoryNotFound(usize),
+    #[error("A password for {} already exists", entry_name(.service, .username))]
+    DuplicateEntry {
+        service: String,
+        username: Option<String>,
+    },
+    #[error("This password has no TOTP secret")]
+    NoTotp,
+    #[error("This password has no HOTP secret")]
+    NoHotp,
+    #[error("Failed to resync the HOTP counter: {0}")]
+    HotpResync(String),
+    #[error("Invalid one-time password secret: {0}")]
+    InvalidOtpSecret(String),
+    #[error("{0}")]
+    Clipboard(String),
+    #[error("Failed to generate password: {0}")]
+    PasswordGeneration(String),
+    /// Reading from the terminal failed, for example because there is no TTY.
+    #[error("Failed to read {prompt}: {source}")]
+    Input {
+        prompt: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to find the home directory: {0}")]
+    HomeDir(#[from] std::env::VarError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, LockboxError>;
+
+impl From<tempfile::PersistError> for LockboxError {
+    fn from(err: tempfile::PersistError) -> Self {
+        LockboxError::Io(err.error)
+    }
+}
+
+fn locked_by(pid: &Option<u32>) -> String {
+    match pid {
+        Some(pid) => format!("pid {pid}"),
+        None => "another process".to_string(),
+    }
+}
+
+fn entry_name(service: &str, username: &Option<String>) -> String {
+    match username {
+        Some(username) => format!("{service} ({username})"),
+        None => service.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        error,
+        expected,
+        case(LockboxError::Locked { pid: Some(42) }, "Password store is locked by pid 42"),
+        case(LockboxError::Locked { pid: None }, "Password store is locked by another process"),
+        case(
+            LockboxError::DuplicateEntry { service: "github".to_string(), username: Some("octocat".to_string()) },
+            "A password for github (octocat) already exists"
+        ),
+        case(
+            LockboxError::InvalidBackup { number: 2, source: Box::new(LockboxError::WrongPassword) },
+            "Failed to open backup 2: Master password incorrect. Please try again."
+        ),
+        case(LockboxError::CorruptStore("unknown cipher id 9".to_string()), "Password store file is corrupt: unknown cipher id 9")
+    )]
+    fn test_error_messages(error: LockboxError, expected: &str) {
+        assert_eq!(error.to_string(), expected);
+    }
+}
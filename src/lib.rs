@@ -1,5 +1,6 @@
 pub mod cli;
 pub mod crypto;
+pub mod error;
 pub mod pass;
 pub mod repl;
 pub mod store;
@@ -5,10 +5,12 @@ use lock_box::{
         io::RpasswordPromptPassword,
         run_cli,
     },
+    crypto::{memory::disable_core_dumps, Cipher},
     repl::repl,
 };
 
 fn main() {
+    disable_core_dumps();
     let mut input = std::io::stdin().lock();
     let mut output = std::io::stdout().lock();
     let prompt_password = &RpasswordPromptPassword;
@@ -18,6 +20,8 @@ fn main() {
             &mut output,
             prompt_password,
             DEFAULT_PASSWORD_FILENAME.to_string(),
+            None,
+            Cipher::default(),
         )
     } else {
         let args = Args::parse();
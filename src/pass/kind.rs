@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use zeroize::Zeroize;
+
+/// What an entry holds, without the fields that come with it. Used to pick a kind on the
+/// command line and to filter `list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Kind {
+    #[default]
+    Login,
+    Note,
+    Card,
+    Identity,
+    SshKey,
+    ApiToken,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Login => write!(f, "login"),
+            Kind::Note => write!(f, "secure note"),
+            Kind::Card => write!(f, "card"),
+            Kind::Identity => write!(f, "identity"),
+            Kind::SshKey => write!(f, "SSH key"),
+            Kind::ApiToken => write!(f, "API token"),
+        }
+    }
+}
+
+/// What an entry holds, with the fields only that kind has. The secret itself is always the
+/// entry's password: the text of a note, the card number, the private key or the token.
+///
+/// Entries of stores written before kinds existed have no `type` and load as logins.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default, Zeroize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum EntryKind {
+    #[default]
+    Login,
+    SecureNote,
+    Card {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cardholder: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expiry: Option<CardExpiry>,
+    },
+    Identity {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        full_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        phone: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+    },
+    SshKey {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        public_key: Option<String>,
+    },
+    ApiToken {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_id: Option<String>,
+    },
+}
+
+impl EntryKind {
+    pub fn kind(&self) -> Kind {
+        match self {
+            EntryKind::Login => Kind::Login,
+            EntryKind::SecureNote => Kind::Note,
+            EntryKind::Card { .. } => Kind::Card,
+            EntryKind::Identity { .. } => Kind::Identity,
+            EntryKind::SshKey { .. } => Kind::SshKey,
+            EntryKind::ApiToken { .. } => Kind::ApiToken,
+        }
+    }
+
+    pub fn is_login(&self) -> bool {
+        *self == EntryKind::Login
+    }
+
+    /// What the password of an entry of this kind is called when it is printed or asked for.
+    pub fn secret_label(&self) -> &'static str {
+        match self {
+            EntryKind::Login => "Password",
+            EntryKind::SecureNote => "Note",
+            EntryKind::Card { .. } => "Card number",
+            EntryKind::Identity { .. } => "ID number",
+            EntryKind::SshKey { .. } => "Private key",
+            EntryKind::ApiToken { .. } => "Token",
+        }
+    }
+
+    /// The fields of this kind that are set, as label and value.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        let fields = match self {
+            EntryKind::Login | EntryKind::SecureNote => vec![],
+            EntryKind::Card { cardholder, expiry } => vec![
+                ("Cardholder", cardholder.clone()),
+                ("Expiry", expiry.as_ref().map(CardExpiry::to_string)),
+            ],
+            EntryKind::Identity {
+                full_name,
+                email,
+                phone,
+                address,
+            } => vec![
+                ("Full name", full_name.clone()),
+                ("Email", email.clone()),
+                ("Phone", phone.clone()),
+                ("Address", address.clone()),
+            ],
+            EntryKind::SshKey { public_key } => vec![("Public key", public_key.clone())],
+            EntryKind::ApiToken { key_id } => vec![("Key ID", key_id.clone())],
+        };
+        fields
+            .into_iter()
+            .filter_map(|(label, value)| Some((label, value?)))
+            .collect()
+    }
+}
+
+/// The month and year a payment card expires, written as `MM/YY` or `MM/YYYY`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Zeroize)]
+pub struct CardExpiry {
+    pub month: u8,
+    pub year: u16,
+}
+
+impl FromStr for CardExpiry {
+    type Err = String;
+
+    fn from_str(expiry: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected the expiry as MM/YY or MM/YYYY, got `{expiry}`");
+        let (month, year) = expiry.split_once('/').ok_or_else(invalid)?;
+        let month: u8 = month.trim().parse().map_err(|_| invalid())?;
+        let year = year.trim();
+        let year: u16 = match (year.len(), year.parse::<u16>()) {
+            (2, Ok(year)) => 2000 + year,
+            (4, Ok(year)) => year,
+            _ => return Err(invalid()),
+        };
+        if !(1..=12).contains(&month) {
+            return Err(invalid());
+        }
+        Ok(CardExpiry { month, year })
+    }
+}
+
+impl Display for CardExpiry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}/{}", self.month, self.year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        input,
+        expected,
+        case("04/27", Some(CardExpiry { month: 4, year: 2027 })),
+        case("12/2031", Some(CardExpiry { month: 12, year: 2031 })),
+        case("13/27", None),
+        case("00/27", None),
+        case("4/271", None),
+        case("0427", None)
+    )]
+    fn test_parse_card_expiry(input: &str, expected: Option<CardExpiry>) {
+        assert_eq!(input.parse::<CardExpiry>().ok(), expected);
+    }
+
+    #[test]
+    fn test_entry_kind_serialization() {
+        let kind = EntryKind::Card {
+            cardholder: Some("Jane Doe".to_string()),
+            expiry: Some(CardExpiry {
+                month: 4,
+                year: 2027,
+            }),
+        };
+        let json = serde_json::to_string(&kind).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"card","cardholder":"Jane Doe","expiry":{"month":4,"year":2027}}"#
+        );
+        assert_eq!(serde_json::from_str::<EntryKind>(&json).unwrap(), kind);
+        assert_eq!(
+            kind.details(),
+            vec![
+                ("Cardholder", "Jane Doe".to_string()),
+                ("Expiry", "04/2027".to_string())
+            ]
+        );
+        assert_eq!(
+            serde_json::from_str::<EntryKind>(r#"{"type":"secure-note"}"#).unwrap(),
+            EntryKind::SecureNote
+        );
+    }
+}
@@ -1,31 +1,402 @@
+mod kind;
+mod otp;
+
+pub use kind::{CardExpiry, EntryKind, Kind};
+pub use otp::{Hotp, OtpAlgorithm, Totp, RESYNC_WINDOW};
+
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
-use crate::cli::commands::copy_to_clipboard;
+use crate::cli::commands::{copy_to_clipboard, format_age};
 use crate::cli::io::{print, print_key_value_with_color, MessageType};
+use crate::crypto::derive_id;
+use crate::error::{LockboxError, Result};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// The plaintext password is wiped from memory when the entry is dropped.
+///
+/// Everything but the service, username and password was added later, so those fields default
+/// to empty when older stores are parsed. Timestamps are seconds since the Unix epoch. The id
+/// is assigned when the entry is added to a store, or when an older store is first loaded.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Zeroize, ZeroizeOnDrop)]
 pub struct PasswordEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[zeroize(skip)]
+    pub id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "EntryKind::is_login")]
+    pub kind: EntryKind,
     pub service: String,
     pub username: Option<String>,
     password: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<CustomField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<Totp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotp: Option<Hotp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    /// Earlier passwords, oldest first, up to `MAX_HISTORY` of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<PreviousPassword>,
+}
+
+/// A named value stored along with the password, like a security question answer or an
+/// account number. Concealed fields are masked wherever the password is.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Zeroize, ZeroizeOnDrop)]
+pub struct CustomField {
+    pub name: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub concealed: bool,
+}
+
+impl CustomField {
+    pub fn new(name: String, value: String, concealed: bool) -> Self {
+        CustomField {
+            name,
+            value,
+            concealed,
+        }
+    }
+
+    /// The value, or `***` if the field is concealed and `reveal` is false.
+    fn display_value(&self, reveal: bool) -> &str {
+        match self.concealed && !reveal {
+            true => "***",
+            false => &self.value,
+        }
+    }
+}
+
+/// How many replaced passwords an entry remembers.
+pub const MAX_HISTORY: usize = 10;
+
+/// A password an entry had before, with the time it was replaced.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Zeroize, ZeroizeOnDrop)]
+pub struct PreviousPassword {
+    password: String,
+    pub replaced: u64,
+}
+
+/// Picks an entry either by its id or by its service and username.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryRef {
+    Id(Uuid),
+    Name {
+        service: String,
+        username: Option<String>,
+    },
+}
+
+impl EntryRef {
+    pub fn name(service: String, username: Option<String>) -> Self {
+        EntryRef::Name { service, username }
+    }
+
+    fn matches(&self, entry: &PasswordEntry) -> bool {
+        match self {
+            EntryRef::Id(id) => entry.id == Some(*id),
+            EntryRef::Name { service, username } => {
+                entry.service == *service && entry.username == *username
+            }
+        }
+    }
+}
+
+/// What to do when adding an entry whose service and username are already taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OnDuplicate {
+    #[default]
+    Fail,
+    /// Replace the password of the existing entry, and its details where new ones are given.
+    Overwrite,
+    /// Add the entry under a new service name, like `github (2)`.
+    KeepBoth,
+}
+
+/// The optional details of an entry that are set when it is added.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EntryDetails {
+    pub kind: EntryKind,
+    pub urls: Vec<String>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub fields: Vec<CustomField>,
+    pub totp: Option<Totp>,
+    pub hotp: Option<Hotp>,
+}
+
+/// The changes `edit` makes to an entry. Fields left as `None` stay as they are, and an empty
+/// username or notes clears them. Custom fields are set by name, and one with an empty value is
+/// removed. A TOTP or HOTP secret replaces the one the entry has.
+#[derive(Debug, Default, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
+pub struct EntryChanges {
+    pub password: Option<String>,
+    pub service: Option<String>,
+    pub username: Option<String>,
+    pub urls: Option<Vec<String>>,
+    pub notes: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub fields: Vec<CustomField>,
+    pub totp: Option<Totp>,
+    pub hotp: Option<Hotp>,
+}
+
+impl EntryChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == EntryChanges::default()
+    }
 }
 
 impl PasswordEntry {
     pub fn new(service: String, username: Option<String>, password: String) -> PasswordEntry {
+        let now = now();
         PasswordEntry {
+            id: None,
+            kind: EntryKind::Login,
             service,
             username,
             password,
+            urls: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
+            fields: Vec::new(),
+            totp: None,
+            hotp: None,
+            created: Some(now),
+            modified: Some(now),
+            last_used: None,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn with_details(mut self, details: EntryDetails) -> Self {
+        self.kind = details.kind;
+        self.urls = details.urls;
+        self.notes = details.notes;
+        self.tags = details.tags;
+        self.totp = details.totp;
+        self.hotp = details.hotp;
+        for field in details.fields {
+            self.set_field(field);
+        }
+        self
+    }
+
+    /// Adds a custom field or replaces the one with the same name. A field with an empty value
+    /// removes it instead.
+    pub fn set_field(&mut self, field: CustomField) {
+        let existing = self.fields.iter().position(|f| f.name == field.name);
+        match (existing, field.value.is_empty()) {
+            (Some(index), true) => {
+                self.fields.remove(index);
+            }
+            (Some(index), false) => self.fields[index] = field,
+            (None, true) => (),
+            (None, false) => self.fields.push(field),
         }
     }
+
+    /// Replaces the password and bumps the modified time. The old password goes to the history,
+    /// which drops its oldest entry once it holds `MAX_HISTORY`.
+    pub fn set_password(&mut self, password: String) {
+        let now = now();
+        if password != self.password {
+            let previous = std::mem::replace(&mut self.password, password);
+            self.history.push(PreviousPassword {
+                password: previous,
+                replaced: now,
+            });
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.modified = Some(now);
+    }
+
+    /// The earlier passwords, most recent first, so `history()[0]` is number 1.
+    pub fn history(&self) -> impl Iterator<Item = &PreviousPassword> {
+        self.history.iter().rev()
+    }
+
+    /// Prints the earlier passwords, numbered the way `restore_password` takes them.
+    pub fn print_history<W: Write>(
+        &self,
+        writer: &mut W,
+        show_passwords: bool,
+        message_type: Option<MessageType>,
+    ) {
+        if self.history.is_empty() {
+            print(writer, "No previous passwords", Some(MessageType::Warning));
+            return;
+        }
+        for (number, previous) in self.history().enumerate() {
+            print_key_value_with_color(
+                writer,
+                "Number",
+                &(number + 1).to_string(),
+                None,
+                message_type,
+                Some(", "),
+            );
+            print_key_value_with_color(
+                writer,
+                "Replaced",
+                &format_timestamp(previous.replaced),
+                None,
+                message_type,
+                Some(", "),
+            );
+            let password = match show_passwords {
+                true => previous.password.as_str(),
+                false => "***",
+            };
+            print_key_value_with_color(writer, "Password", password, None, message_type, None);
+        }
+    }
+
+    /// Makes an earlier password current again, counting from 1 for the most recent one. The
+    /// password it replaces goes to the history, so a restore can be undone the same way.
+    pub fn restore_password(&mut self, number: usize) -> Result<()> {
+        let index = number
+            .checked_sub(1)
+            .and_then(|offset| self.history.len().checked_sub(offset + 1))
+            .ok_or(LockboxError::HistoryNotFound(number))?;
+        let mut previous = self.history.remove(index);
+        self.set_password(std::mem::take(&mut previous.password));
+        Ok(())
+    }
+
+    /// Applies the changes and bumps the modified time.
+    pub fn apply(&mut self, mut changes: EntryChanges) {
+        if let Some(password) = changes.password.take() {
+            self.set_password(password);
+        }
+        if let Some(service) = changes.service.take() {
+            self.service = service;
+        }
+        if let Some(username) = changes.username.take() {
+            self.username = Some(username).filter(|username| !username.is_empty());
+        }
+        if let Some(urls) = changes.urls.take() {
+            self.urls = urls;
+        }
+        if let Some(notes) = changes.notes.take() {
+            self.notes = Some(notes).filter(|notes| !notes.is_empty());
+        }
+        if let Some(tags) = changes.tags.take() {
+            self.tags = tags;
+        }
+        for field in std::mem::take(&mut changes.fields) {
+            self.set_field(field);
+        }
+        if let Some(totp) = changes.totp.take() {
+            self.totp = Some(totp);
+        }
+        if let Some(hotp) = changes.hotp.take() {
+            self.hotp = Some(hotp);
+        }
+        self.modified = Some(now());
+    }
+
+    pub fn mark_used(&mut self) {
+        self.last_used = Some(now());
+    }
+
     pub fn print_password<W: Write>(&self, writer: &mut W, message_type: Option<MessageType>) {
-        print_key_value_with_color(writer, "Password", &self.password, None, message_type, None);
+        print_key_value_with_color(
+            writer,
+            self.kind.secret_label(),
+            &self.password,
+            None,
+            message_type,
+            None,
+        );
     }
 
-    pub fn copy_password(&self) -> anyhow::Result<()> {
-        copy_to_clipboard(self.password.clone())
+    /// Prints the details that are set, one per line, masking concealed fields unless `reveal`.
+    pub fn print_details<W: Write>(
+        &self,
+        writer: &mut W,
+        reveal: bool,
+        message_type: Option<MessageType>,
+    ) {
+        let mut details = Vec::new();
+        if let Some(id) = self.id {
+            details.push(("ID", id.to_string()));
+        }
+        if !self.kind.is_login() {
+            details.push(("Kind", self.kind.kind().to_string()));
+        }
+        details.extend(self.kind.details());
+        for url in &self.urls {
+            details.push(("URL", url.clone()));
+        }
+        if let Some(notes) = &self.notes {
+            details.push(("Notes", notes.clone()));
+        }
+        if !self.tags.is_empty() {
+            details.push(("Tags", self.tags.join(" ")));
+        }
+        for field in &self.fields {
+            details.push((&field.name, field.display_value(reveal).to_string()));
+        }
+        if let Some(totp) = &self.totp {
+            details.push(("TOTP", totp.describe()));
+        }
+        if let Some(hotp) = &self.hotp {
+            details.push(("HOTP", hotp.describe()));
+        }
+        let timestamps = [
+            ("Created", self.created),
+            ("Modified", self.modified),
+            ("Last used", self.last_used),
+        ];
+        for (key, timestamp) in timestamps {
+            if let Some(timestamp) = timestamp {
+                details.push((key, format_timestamp(timestamp)));
+            }
+        }
+        if !self.history.is_empty() {
+            details.push((
+                "History",
+                format!("{} previous passwords", self.history.len()),
+            ));
+        }
+        for (key, value) in details {
+            print_key_value_with_color(writer, key, &value, None, message_type, None);
+        }
     }
+
+    pub fn copy_password(&self) -> Result<()> {
+        copy_to_clipboard(&self.password)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    let age = Duration::from_secs(now().saturating_sub(timestamp));
+    format_age(age)
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -52,37 +423,181 @@ impl Passwords {
         self.0.push(new_password);
     }
 
-    pub fn find(&self, service: String, username: Option<String>) -> Option<&PasswordEntry> {
-        self.0
-            .iter()
-            .find(|pwd| pwd.service == service && pwd.username == username)
+    /// Adds an entry unless its service and username are taken, in which case `on_duplicate`
+    /// decides what happens. Returns the entry that now holds the password.
+    pub fn insert(
+        &mut self,
+        mut entry: PasswordEntry,
+        on_duplicate: OnDuplicate,
+    ) -> Result<&PasswordEntry> {
+        let name = EntryRef::name(entry.service.clone(), entry.username.clone());
+        let index = match (
+            self.0.iter().position(|pwd| name.matches(pwd)),
+            on_duplicate,
+        ) {
+            (None, _) => {
+                self.0.push(entry);
+                self.0.len() - 1
+            }
+            (Some(_), OnDuplicate::Fail) => {
+                return Err(LockboxError::DuplicateEntry {
+                    service: entry.service.clone(),
+                    username: entry.username.clone(),
+                })
+            }
+            (Some(index), OnDuplicate::Overwrite) => {
+                // Move the new values over, so no copy of a secret is left behind
+                let existing = &mut self.0[index];
+                existing.set_password(std::mem::take(&mut entry.password));
+                if !entry.urls.is_empty() {
+                    existing.urls = std::mem::take(&mut entry.urls);
+                }
+                if entry.notes.is_some() {
+                    existing.notes = entry.notes.take();
+                }
+                if !entry.tags.is_empty() {
+                    existing.tags = std::mem::take(&mut entry.tags);
+                }
+                if !entry.fields.is_empty() {
+                    existing.fields = std::mem::take(&mut entry.fields);
+                }
+                if !entry.kind.is_login() {
+                    existing.kind = std::mem::take(&mut entry.kind);
+                }
+                if entry.totp.is_some() {
+                    existing.totp = entry.totp.take();
+                }
+                if entry.hotp.is_some() {
+                    existing.hotp = entry.hotp.take();
+                }
+                index
+            }
+            (Some(_), OnDuplicate::KeepBoth) => {
+                entry.service = self.free_service_name(&entry.service, &entry.username);
+                self.0.push(entry);
+                self.0.len() - 1
+            }
+        };
+        Ok(&self.0[index])
     }
 
-    pub fn remove(&mut self, service: String, username: Option<String>) -> Option<PasswordEntry> {
-        if let Some(index) = self
+    /// The first of `service (2)`, `service (3)`, ... that has no entry for `username`.
+    fn free_service_name(&self, service: &str, username: &Option<String>) -> String {
+        (2..)
+            .map(|number| format!("{service} ({number})"))
+            .find(|candidate| self.find(candidate.clone(), username.clone()).is_none())
+            .unwrap_or_default()
+    }
+
+    /// Changes an entry in place, keeping its id and created time. Fails if it would get the
+    /// service and username of another entry.
+    pub fn edit(&mut self, entry: &EntryRef, changes: EntryChanges) -> Result<&PasswordEntry> {
+        let index = self
+            .0
+            .iter()
+            .position(|pwd| entry.matches(pwd))
+            .ok_or(LockboxError::EntryNotFound)?;
+        let current = &self.0[index];
+        let service = changes
+            .service
+            .clone()
+            .unwrap_or_else(|| current.service.clone());
+        let username = match &changes.username {
+            Some(username) => Some(username.clone()).filter(|username| !username.is_empty()),
+            None => current.username.clone(),
+        };
+        let taken = self
             .0
             .iter()
-            .position(|pwd| pwd.service == service && pwd.username == username)
-        {
+            .enumerate()
+            .any(|(i, pwd)| i != index && pwd.service == service && pwd.username == username);
+        if taken {
+            return Err(LockboxError::DuplicateEntry { service, username });
+        }
+        self.0[index].apply(changes);
+        Ok(&self.0[index])
+    }
+
+    pub fn find(&self, service: String, username: Option<String>) -> Option<&PasswordEntry> {
+        self.get(&EntryRef::name(service, username))
+    }
+
+    pub fn get(&self, entry: &EntryRef) -> Option<&PasswordEntry> {
+        self.0.iter().find(|pwd| entry.matches(pwd))
+    }
+
+    pub fn get_mut(&mut self, entry: &EntryRef) -> Option<&mut PasswordEntry> {
+        self.0.iter_mut().find(|pwd| entry.matches(pwd))
+    }
+
+    pub fn remove(&mut self, entry: &EntryRef) -> Option<PasswordEntry> {
+        if let Some(index) = self.0.iter().position(|pwd| entry.matches(pwd)) {
             Some(self.0.remove(index))
         } else {
             None
         }
     }
 
-    pub fn parse_passwords(raw_passwords: &str) -> Result<Passwords, anyhow::Error> {
-        let passwords: Passwords = serde_json::from_str(raw_passwords)?;
+    /// Gives every entry without an id one, returning whether any entry changed. The id is
+    /// derived from the position, service and username of the entry, so it stays the same
+    /// every time an unchanged store is loaded until a write saves it.
+    pub fn assign_missing_ids(&mut self) -> bool {
+        let mut assigned = false;
+        for (index, entry) in self.0.iter_mut().enumerate() {
+            if entry.id.is_none() {
+                let name = format!(
+                    "{index}\0{}\0{}",
+                    entry.service,
+                    entry.username.as_deref().unwrap_or_default()
+                );
+                entry.id = Some(derive_id(name.as_bytes()));
+                assigned = true;
+            }
+        }
+        assigned
+    }
+
+    pub fn parse_passwords(raw_passwords: &str) -> Result<Passwords> {
+        let passwords: Passwords = serde_json::from_str(raw_passwords)
+            .map_err(|err| LockboxError::CorruptStore(format!("invalid passwords: {err}")))?;
         Ok(passwords)
     }
 
+    /// Prints every entry, or only those of `kind`, one per line.
     pub fn print_all<W: Write>(
         &self,
         writer: &mut W,
         show_passwords: bool,
+        kind: Option<Kind>,
         message_type: Option<MessageType>,
     ) {
-        if !self.0.is_empty() {
-            for pwd in self.0.iter() {
+        let entries: Vec<&PasswordEntry> = self
+            .0
+            .iter()
+            .filter(|pwd| kind.is_none_or(|kind| pwd.kind.kind() == kind))
+            .collect();
+        if !entries.is_empty() {
+            for pwd in entries {
+                if let Some(id) = pwd.id {
+                    print_key_value_with_color(
+                        writer,
+                        "ID",
+                        &id.to_string(),
+                        None,
+                        message_type,
+                        Some(","),
+                    );
+                }
+                if !pwd.kind.is_login() {
+                    print_key_value_with_color(
+                        writer,
+                        "Kind",
+                        &pwd.kind.kind().to_string(),
+                        None,
+                        message_type,
+                        Some(","),
+                    );
+                }
                 print_key_value_with_color(
                     writer,
                     "Service",
@@ -91,28 +606,51 @@ impl Passwords {
                     message_type,
                     Some(","),
                 );
-                if pwd.username.is_some() {
+                if let Some(username) = &pwd.username {
                     print_key_value_with_color(
                         writer,
                         "Username",
-                        pwd.username.as_ref().unwrap(),
+                        username,
                         None,
                         message_type,
                         Some(","),
                     );
                 }
-                if show_passwords {
+                if let Some(url) = pwd.urls.first() {
+                    print_key_value_with_color(writer, "URL", url, None, message_type, Some(","));
+                }
+                if !pwd.tags.is_empty() {
                     print_key_value_with_color(
                         writer,
-                        "Password",
-                        &pwd.password,
+                        "Tags",
+                        &pwd.tags.join(" "),
                         None,
                         message_type,
+                        Some(","),
+                    );
+                }
+                for field in &pwd.fields {
+                    print_key_value_with_color(
+                        writer,
+                        &field.name,
+                        field.display_value(show_passwords),
                         None,
+                        message_type,
+                        Some(","),
                     );
-                } else {
-                    print_key_value_with_color(writer, "Password", "***", None, message_type, None);
                 }
+                let password = match show_passwords {
+                    true => pwd.password.as_str(),
+                    false => "***",
+                };
+                print_key_value_with_color(
+                    writer,
+                    pwd.kind.secret_label(),
+                    password,
+                    None,
+                    message_type,
+                    None,
+                );
             }
         } else {
             print(writer, "No passwords found!", Some(MessageType::Warning));
@@ -131,6 +669,259 @@ mod tests {
         assert_eq!(Passwords::default(), Passwords(vec![]));
     }
 
+    #[test]
+    fn test_parse_passwords_without_details() {
+        let passwords = Passwords::parse_passwords(
+            r#"[{"service":"service1","username":null,"password":"password1"}]"#,
+        )
+        .unwrap();
+        let entry = passwords.find("service1".to_string(), None).unwrap();
+        assert!(entry.urls.is_empty() && entry.tags.is_empty());
+        assert_eq!(entry.notes, None);
+        assert_eq!(
+            (entry.created, entry.modified, entry.last_used),
+            (None, None, None)
+        );
+    }
+
+    #[rstest(
+        on_duplicate,
+        expected_services,
+        expected_password,
+        case(OnDuplicate::Overwrite, vec!["service1", "service2"], "new_password"),
+        case(OnDuplicate::KeepBoth, vec!["service1", "service2", "service1 (2)"], "password1")
+    )]
+    fn test_insert_duplicate(
+        on_duplicate: OnDuplicate,
+        expected_services: Vec<&str>,
+        expected_password: &str,
+    ) {
+        let mut passwords = Passwords::from(vec![
+            PasswordEntry::new(
+                "service1".to_string(),
+                Some("username1".to_string()),
+                "password1".to_string(),
+            ),
+            PasswordEntry::new("service2".to_string(), None, "password2".to_string()),
+        ]);
+        let new_entry = || {
+            PasswordEntry::new(
+                "service1".to_string(),
+                Some("username1".to_string()),
+                "new_password".to_string(),
+            )
+            .with_details(EntryDetails {
+                notes: Some("new notes".to_string()),
+                ..Default::default()
+            })
+        };
+        assert!(matches!(
+            passwords.insert(new_entry(), OnDuplicate::Fail),
+            Err(LockboxError::DuplicateEntry { service, username })
+                if service == "service1" && username.as_deref() == Some("username1")
+        ));
+
+        let stored = passwords.insert(new_entry(), on_duplicate).unwrap();
+        assert_eq!(stored.password, "new_password");
+        assert_eq!(stored.notes.as_deref(), Some("new notes"));
+        let services: Vec<&str> = passwords.0.iter().map(|pwd| pwd.service.as_str()).collect();
+        assert_eq!(services, expected_services);
+        let first = passwords
+            .find("service1".to_string(), Some("username1".to_string()))
+            .unwrap();
+        assert_eq!(first.password, expected_password);
+    }
+
+    #[test]
+    fn test_password_history() {
+        let mut entry = PasswordEntry::new("service1".to_string(), None, "password0".to_string());
+        entry.set_password("password0".to_string());
+        assert_eq!(entry.history().count(), 0);
+        for number in 1..=MAX_HISTORY + 2 {
+            entry.set_password(format!("password{number}"));
+        }
+        let history: Vec<&str> = entry
+            .history()
+            .map(|previous| previous.password.as_str())
+            .collect();
+        assert_eq!(history.len(), MAX_HISTORY);
+        assert_eq!(history[0], format!("password{}", MAX_HISTORY + 1));
+        assert_eq!(history[MAX_HISTORY - 1], "password2");
+
+        entry.restore_password(2).unwrap();
+        assert_eq!(entry.password, format!("password{}", MAX_HISTORY));
+        let history: Vec<&str> = entry
+            .history()
+            .map(|previous| previous.password.as_str())
+            .collect();
+        assert_eq!(history[0], format!("password{}", MAX_HISTORY + 2));
+        assert_eq!(history[1], format!("password{}", MAX_HISTORY + 1));
+        assert_eq!(history.len(), MAX_HISTORY);
+        for number in [0, MAX_HISTORY + 1] {
+            assert!(matches!(
+                entry.restore_password(number),
+                Err(LockboxError::HistoryNotFound(n)) if n == number
+            ));
+        }
+
+        let json = serde_json::to_string(&Passwords::from(vec![entry.clone()])).unwrap();
+        let parsed = Passwords::parse_passwords(&json).unwrap();
+        assert_eq!(parsed.find("service1".to_string(), None), Some(&entry));
+
+        let mut output = Vec::new();
+        entry.print_history(&mut output, false, None);
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Number: 1, Replaced: just now, Password: ***"));
+        assert!(!output_str.contains("password2"));
+    }
+
+    #[test]
+    fn test_entry_details() {
+        let mut entry = PasswordEntry::new(
+            "service1".to_string(),
+            Some("username1".to_string()),
+            "password1".to_string(),
+        )
+        .with_details(EntryDetails {
+            urls: vec!["https://example.com".to_string()],
+            notes: Some("shared with the team".to_string()),
+            tags: vec!["work".to_string(), "email".to_string()],
+            fields: vec![
+                CustomField::new("account".to_string(), "12345".to_string(), false),
+                CustomField::new("pin".to_string(), "0000".to_string(), true),
+            ],
+            ..Default::default()
+        });
+        assert!(entry.created.is_some());
+        assert_eq!(entry.created, entry.modified);
+        assert_eq!(entry.last_used, None);
+        entry.mark_used();
+        assert!(entry.last_used.is_some());
+
+        let json = serde_json::to_string(&Passwords::from(vec![entry.clone()])).unwrap();
+        let parsed = Passwords::parse_passwords(&json).unwrap();
+        assert_eq!(
+            parsed.find("service1".to_string(), Some("username1".to_string())),
+            Some(&entry)
+        );
+
+        let mut output = Vec::new();
+        entry.print_details(&mut output, false, None);
+        let output_str = String::from_utf8(output).unwrap();
+        for expected in [
+            "URL: https://example.com",
+            "Notes: shared with the team",
+            "Tags: work email",
+            "account: 12345",
+            "pin: ***",
+            "Created: just now",
+            "Modified: just now",
+            "Last used: just now",
+        ] {
+            assert!(output_str.contains(expected), "{output_str}");
+        }
+
+        let mut output = Vec::new();
+        parsed.print_all(&mut output, false, None, None);
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("URL: https://example.com,Tags: work email,"));
+        assert!(output_str.contains("account: 12345,pin: ***,Password: ***"));
+        assert!(!output_str.contains("0000"));
+
+        let mut output = Vec::new();
+        parsed.print_all(&mut output, true, None, None);
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("account: 12345,pin: 0000,Password: password1"));
+    }
+
+    #[test]
+    fn test_entry_kinds() {
+        let card = PasswordEntry::new("visa".to_string(), None, "4111111111111111".to_string())
+            .with_details(EntryDetails {
+                kind: EntryKind::Card {
+                    cardholder: Some("Jane Doe".to_string()),
+                    expiry: Some("04/27".parse().unwrap()),
+                },
+                ..Default::default()
+            });
+        let note = PasswordEntry::new("wifi".to_string(), None, "hunter2".to_string())
+            .with_details(EntryDetails {
+                kind: EntryKind::SecureNote,
+                ..Default::default()
+            });
+        let login = PasswordEntry::new("github".to_string(), None, "password1".to_string());
+
+        let mut output = Vec::new();
+        card.print_password(&mut output, None);
+        card.print_details(&mut output, false, None);
+        let output_str = String::from_utf8(output).unwrap();
+        for expected in [
+            "Card number: 4111111111111111",
+            "Kind: card",
+            "Cardholder: Jane Doe",
+            "Expiry: 04/2027",
+        ] {
+            assert!(output_str.contains(expected), "{output_str}");
+        }
+
+        let json = serde_json::to_string(&Passwords::from(vec![card, note, login])).unwrap();
+        assert_eq!(json.matches(r#""kind""#).count(), 2);
+        let parsed = Passwords::parse_passwords(&json).unwrap();
+        let mut output = Vec::new();
+        parsed.print_all(&mut output, true, Some(Kind::Note), None);
+        let output_str = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output_str,
+            "Kind: secure note,Service: wifi,Note: hunter2\n",
+        );
+        let mut output = Vec::new();
+        parsed.print_all(&mut output, false, Some(Kind::SshKey), None);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("No passwords found!"));
+    }
+
+    #[test]
+    fn test_set_field() {
+        let mut entry = PasswordEntry::new("service1".to_string(), None, "password1".to_string());
+        entry.set_field(CustomField::new(
+            "pin".to_string(),
+            "0000".to_string(),
+            true,
+        ));
+        entry.set_field(CustomField::new(
+            "account".to_string(),
+            "1".to_string(),
+            false,
+        ));
+        entry.set_field(CustomField::new(
+            "pin".to_string(),
+            "1234".to_string(),
+            true,
+        ));
+        assert_eq!(
+            entry.fields,
+            vec![
+                CustomField::new("pin".to_string(), "1234".to_string(), true),
+                CustomField::new("account".to_string(), "1".to_string(), false),
+            ]
+        );
+        entry.set_field(CustomField::new("pin".to_string(), "".to_string(), false));
+        entry.set_field(CustomField::new(
+            "missing".to_string(),
+            "".to_string(),
+            false,
+        ));
+        assert_eq!(
+            entry.fields,
+            vec![CustomField::new(
+                "account".to_string(),
+                "1".to_string(),
+                false
+            )]
+        );
+    }
+
     #[rstest(
         test_passwords,
         show_passwords,
@@ -223,7 +1014,7 @@ mod tests {
     fn test_print_all(test_passwords: Vec<PasswordEntry>, show_passwords: bool) {
         let passwords = Passwords::from(test_passwords);
         let mut output = Vec::new();
-        passwords.print_all(&mut output, show_passwords, None);
+        passwords.print_all(&mut output, show_passwords, None, None);
         let output_str = String::from_utf8(output).unwrap();
         for password in passwords.0 {
             if show_passwords {
@@ -233,7 +1024,7 @@ mod tests {
             };
             assert!(output_str.contains(&password.service));
             if password.username.is_some() {
-                assert!(output_str.contains(&password.username.unwrap()))
+                assert!(output_str.contains(password.username.as_deref().unwrap()))
             };
         }
     }
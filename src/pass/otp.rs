@@ -0,0 +1,513 @@
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::percent_decode_str;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::{LockboxError, Result};
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+/// The hash function the one-time password codes are computed with.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OtpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgorithm {
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            OtpAlgorithm::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            OtpAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            OtpAlgorithm::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl FromStr for OtpAlgorithm {
+    type Err = String;
+
+    fn from_str(algorithm: &str) -> std::result::Result<Self, Self::Err> {
+        match algorithm.to_uppercase().as_str() {
+            "SHA1" => Ok(OtpAlgorithm::Sha1),
+            "SHA256" => Ok(OtpAlgorithm::Sha256),
+            "SHA512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(format!(
+                "unsupported algorithm `{algorithm}`, expected SHA1, SHA256 or SHA512"
+            )),
+        }
+    }
+}
+
+impl Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpAlgorithm::Sha1 => write!(f, "SHA1"),
+            OtpAlgorithm::Sha256 => write!(f, "SHA256"),
+            OtpAlgorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+/// A time-based one-time password secret (RFC 6238), as authenticator apps set it up.
+///
+/// It is parsed from an `otpauth://totp/...` URI, or from the bare base32 secret for the usual
+/// SHA1, 6 digits and 30 seconds. The secret is kept base32 encoded, without padding.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Zeroize)]
+pub struct Totp {
+    secret: String,
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub algorithm: OtpAlgorithm,
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    #[serde(default = "default_period")]
+    pub period: u64,
+}
+
+fn default_digits() -> u32 {
+    DEFAULT_DIGITS
+}
+
+fn default_period() -> u64 {
+    DEFAULT_PERIOD
+}
+
+impl Totp {
+    /// The code for the current time, with the seconds it stays valid.
+    pub fn code(&self) -> Result<(String, u64)> {
+        self.code_at(super::now())
+    }
+
+    /// The code for `time`, in seconds since the Unix epoch, with the seconds it stays valid.
+    pub fn code_at(&self, time: u64) -> Result<(String, u64)> {
+        self.check().map_err(LockboxError::InvalidOtpSecret)?;
+        let secret = decode_secret(&self.secret).map_err(LockboxError::InvalidOtpSecret)?;
+        let code = hotp(&secret, self.algorithm, self.digits, time / self.period);
+        Ok((code, self.period - time % self.period))
+    }
+
+    /// The algorithm, digits and period, without the secret.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {} digits, every {} seconds",
+            self.algorithm, self.digits, self.period
+        )
+    }
+
+    /// The digits and period are public and only checked when parsed, so a stored or built
+    /// `Totp` is checked again before a code is made from it.
+    fn check(&self) -> std::result::Result<(), String> {
+        check_digits(self.digits)?;
+        if self.period == 0 {
+            return Err("the period must be at least one second".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Totp {
+    type Err = String;
+
+    fn from_str(totp: &str) -> std::result::Result<Self, Self::Err> {
+        let parameters = OtpParameters::parse(totp, "totp")?;
+        let totp = Totp {
+            secret: parameters.secret,
+            algorithm: parameters.algorithm,
+            digits: parameters.digits,
+            period: parameters.period,
+        };
+        totp.check()?;
+        Ok(totp)
+    }
+}
+
+/// How far `Hotp::resync` looks ahead of the current counter.
+pub const RESYNC_WINDOW: u64 = 1000;
+
+/// A counter-based one-time password secret (RFC 4226), like hardware tokens use.
+///
+/// It is parsed from an `otpauth://hotp/...` URI, or from the bare base32 secret for SHA1,
+/// 6 digits and a counter starting at 0. The counter is the one the next code is made from.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Zeroize)]
+pub struct Hotp {
+    secret: String,
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub algorithm: OtpAlgorithm,
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    #[serde(default)]
+    pub counter: u64,
+}
+
+impl Hotp {
+    /// The code for the current counter, which is then advanced so no code is given twice.
+    pub fn next_code(&mut self) -> Result<String> {
+        let code = self.code_at(self.counter)?;
+        self.counter += 1;
+        Ok(code)
+    }
+
+    pub fn code_at(&self, counter: u64) -> Result<String> {
+        check_digits(self.digits).map_err(LockboxError::InvalidOtpSecret)?;
+        let secret = decode_secret(&self.secret).map_err(LockboxError::InvalidOtpSecret)?;
+        Ok(hotp(&secret, self.algorithm, self.digits, counter))
+    }
+
+    /// Finds the counter that produced `codes`, given in the order they were generated, within
+    /// `RESYNC_WINDOW` ahead of the current one and moves the counter past the last of them.
+    /// Returns the new counter. The counter never goes back, so used codes can't be replayed
+    /// (RFC 4226 §7.4), and a single code could match by chance, so at least two are needed.
+    pub fn resync(&mut self, codes: &[String]) -> Result<u64> {
+        if codes.len() < 2 {
+            return Err(LockboxError::HotpResync(
+                "at least two consecutive codes are needed".to_string(),
+            ));
+        }
+        check_digits(self.digits).map_err(LockboxError::InvalidOtpSecret)?;
+        let secret = decode_secret(&self.secret).map_err(LockboxError::InvalidOtpSecret)?;
+        let matches = |start: u64| {
+            codes.iter().zip(start..).all(|(code, counter)| {
+                code.trim() == hotp(&secret, self.algorithm, self.digits, counter)
+            })
+        };
+        let start = (self.counter..self.counter.saturating_add(RESYNC_WINDOW))
+            .find(|start| matches(*start))
+            .ok_or_else(|| {
+                LockboxError::HotpResync(format!(
+                    "the codes don't match any counter within {RESYNC_WINDOW} after {}",
+                    self.counter
+                ))
+            })?;
+        self.counter = start + codes.len() as u64;
+        Ok(self.counter)
+    }
+
+    /// The algorithm, digits and counter, without the secret.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {} digits, counter {}",
+            self.algorithm, self.digits, self.counter
+        )
+    }
+}
+
+impl FromStr for Hotp {
+    type Err = String;
+
+    fn from_str(hotp: &str) -> std::result::Result<Self, Self::Err> {
+        let parameters = OtpParameters::parse(hotp, "hotp")?;
+        Ok(Hotp {
+            secret: parameters.secret,
+            algorithm: parameters.algorithm,
+            digits: parameters.digits,
+            counter: parameters.counter,
+        })
+    }
+}
+
+/// What an `otpauth://` URI or a bare base32 secret sets up, with the defaults filled in.
+struct OtpParameters {
+    secret: String,
+    algorithm: OtpAlgorithm,
+    digits: u32,
+    period: u64,
+    counter: u64,
+}
+
+impl OtpParameters {
+    /// Parses `otp`, which must be an `otpauth://<otp_type>/...` URI or a base32 secret.
+    fn parse(otp: &str, otp_type: &str) -> std::result::Result<Self, String> {
+        let otp = otp.trim();
+        let mut parameters = OtpParameters {
+            secret: String::new(),
+            algorithm: OtpAlgorithm::default(),
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+            counter: 0,
+        };
+        let Some(uri) = strip_prefix_ignore_case(otp, "otpauth://") else {
+            decode_secret(otp)?;
+            parameters.secret = normalize_secret(otp);
+            return Ok(parameters);
+        };
+        let (uri_type, rest) = uri.split_once('/').unwrap_or((uri, ""));
+        if !uri_type.eq_ignore_ascii_case(otp_type) {
+            return Err(format!(
+                "expected an otpauth://{otp_type} URI, got one for {uri_type}"
+            ));
+        }
+        let query = rest.split_once('?').map_or("", |(_, query)| query);
+        let mut secret = None;
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let value = percent_decode_str(value)
+                .decode_utf8()
+                .map_err(|_| format!("invalid {key} in the otpauth URI"))?;
+            match key.to_lowercase().as_str() {
+                "secret" => secret = Some(value.to_string()),
+                "algorithm" => parameters.algorithm = value.parse()?,
+                "digits" => {
+                    parameters.digits = value
+                        .parse()
+                        .map_err(|_| format!("invalid digits `{value}`"))?
+                }
+                "period" => {
+                    parameters.period = value
+                        .parse()
+                        .map_err(|_| format!("invalid period `{value}`"))?
+                }
+                "counter" => {
+                    parameters.counter = value
+                        .parse()
+                        .map_err(|_| format!("invalid counter `{value}`"))?
+                }
+                // The label, issuer and image only describe the account
+                _ => (),
+            }
+        }
+        let secret = secret.ok_or("the otpauth URI has no secret")?;
+        decode_secret(&secret)?;
+        check_digits(parameters.digits)?;
+        parameters.secret = normalize_secret(&secret);
+        Ok(parameters)
+    }
+}
+
+/// More than 9 digits overflow the `u32` the code is reduced with in `hotp`.
+fn check_digits(digits: u32) -> std::result::Result<(), String> {
+    if !(6..=8).contains(&digits) {
+        return Err(format!("expected 6 to 8 digits, got {digits}"));
+    }
+    Ok(())
+}
+
+/// The HOTP value (RFC 4226) of `counter`, which TOTP computes from the time.
+fn hotp(secret: &[u8], algorithm: OtpAlgorithm, digits: u32, counter: u64) -> String {
+    let key = hmac::Key::new(algorithm.hmac(), secret);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let digest = tag.as_ref();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        truncated % 10u32.pow(digits),
+        width = digits as usize
+    )
+}
+
+/// Base32 secrets are often shown in lowercase, in groups separated by spaces, or padded.
+fn normalize_secret(secret: &str) -> String {
+    let secret = Zeroizing::new(
+        secret
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '=')
+            .collect::<String>(),
+    );
+    secret.to_uppercase()
+}
+
+fn decode_secret(secret: &str) -> std::result::Result<Zeroizing<Vec<u8>>, String> {
+    let secret = Zeroizing::new(
+        BASE32_NOPAD
+            .decode(Zeroizing::new(normalize_secret(secret)).as_bytes())
+            .map_err(|_| "the secret is not valid base32".to_string())?,
+    );
+    if secret.is_empty() {
+        return Err("the secret is empty".to_string());
+    }
+    Ok(secret)
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    match text.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => Some(&text[prefix.len()..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// The test vectors of RFC 6238, appendix B, which use 8 digits and a 30 second period.
+    #[rstest(
+        algorithm,
+        seed,
+        time,
+        expected,
+        case("SHA1", "12345678901234567890", 59, "94287082"),
+        case("SHA256", "12345678901234567890123456789012", 59, "46119246"),
+        case(
+            "SHA512",
+            "1234567890123456789012345678901234567890123456789012345678901234",
+            59,
+            "90693936"
+        ),
+        case("SHA1", "12345678901234567890", 1111111109, "07081804"),
+        case("SHA256", "12345678901234567890123456789012", 1234567890, "91819424"),
+        case(
+            "SHA512",
+            "1234567890123456789012345678901234567890123456789012345678901234",
+            20000000000,
+            "47863826"
+        )
+    )]
+    fn test_totp_rfc_vectors(algorithm: &str, seed: &str, time: u64, expected: &str) {
+        let uri = format!(
+            "otpauth://totp/Example:alice@example.com?secret={}&issuer=Example&algorithm={algorithm}&digits=8&period=30",
+            BASE32_NOPAD.encode(seed.as_bytes())
+        );
+        let totp: Totp = uri.parse().unwrap();
+        let (code, remaining) = totp.code_at(time).unwrap();
+        assert_eq!(code, expected);
+        assert_eq!(remaining, 30 - time % 30);
+    }
+
+    #[rstest(
+        input,
+        expected,
+        case(
+            "jbsw y3dp ehpk 3pxp",
+            Ok(("JBSWY3DPEHPK3PXP", OtpAlgorithm::Sha1, 6, 30))
+        ),
+        case(
+            "OTPAUTH://TOTP/GitHub:octocat?secret=JBSWY3DPEHPK3PXP%3D%3D%3D%3D&algorithm=sha512&digits=7&period=60",
+            Ok(("JBSWY3DPEHPK3PXP", OtpAlgorithm::Sha512, 7, 60))
+        ),
+        case("otpauth://totp/GitHub?issuer=GitHub", Err("the otpauth URI has no secret")),
+        case("otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP&digits=9", Err("expected 6 to 8 digits, got 9")),
+        case("otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP&period=0", Err("the period must be at least one second")),
+        case(
+            "otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP&algorithm=MD5",
+            Err("unsupported algorithm `MD5`, expected SHA1, SHA256 or SHA512")
+        ),
+        case("otpauth://hotp/GitHub?secret=JBSWY3DPEHPK3PXP", Err("expected an otpauth://totp URI, got one for hotp")),
+        case("not base32!", Err("the secret is not valid base32"))
+    )]
+    fn test_parse_totp(
+        input: &str,
+        expected: std::result::Result<(&str, OtpAlgorithm, u32, u64), &str>,
+    ) {
+        let totp = input.parse::<Totp>();
+        let totp = totp
+            .as_ref()
+            .map(|totp| {
+                (
+                    totp.secret.as_str(),
+                    totp.algorithm,
+                    totp.digits,
+                    totp.period,
+                )
+            })
+            .map_err(String::as_str);
+        assert_eq!(totp, expected);
+    }
+
+    #[rstest(
+        json,
+        expected,
+        case(
+            r#"{"secret":"JBSWY3DPEHPK3PXP","period":0}"#,
+            "the period must be at least one second"
+        ),
+        case(
+            r#"{"secret":"JBSWY3DPEHPK3PXP","digits":10}"#,
+            "expected 6 to 8 digits, got 10"
+        )
+    )]
+    fn test_invalid_stored_totp(json: &str, expected: &str) {
+        let totp: Totp = serde_json::from_str(json).unwrap();
+        let err = totp.code_at(59).unwrap_err();
+        assert!(matches!(err, LockboxError::InvalidOtpSecret(message) if message == expected));
+    }
+
+    /// The test values of RFC 4226, appendix D, for counters 0 to 9.
+    const RFC_4226_CODES: [&str; 10] = [
+        "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871",
+        "520489",
+    ];
+
+    fn rfc_4226_hotp(counter: u64) -> Hotp {
+        format!(
+            "otpauth://hotp/Example?secret={}&counter={counter}",
+            BASE32_NOPAD.encode(b"12345678901234567890")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hotp_next_code() {
+        let mut hotp = rfc_4226_hotp(0);
+        for expected in RFC_4226_CODES {
+            assert_eq!(hotp.next_code().unwrap(), expected);
+        }
+        assert_eq!(hotp.counter, 10);
+        assert_eq!(rfc_4226_hotp(7).next_code().unwrap(), RFC_4226_CODES[7]);
+        assert_eq!(
+            "otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP"
+                .parse::<Hotp>()
+                .unwrap_err(),
+            "expected an otpauth://hotp URI, got one for totp"
+        );
+    }
+
+    #[rstest(
+        counter,
+        codes,
+        expected,
+        case(2, vec!["969429", "338314", "254676"], Some(6)),
+        case(0, vec!["162583", "399871"], Some(9)),
+        case(3, vec!["969429", " 338314 "], Some(5)),
+        case(9, vec!["287082", "359152"], None),
+        case(0, vec!["969429", "254676"], None),
+        case(0, vec!["969429"], None)
+    )]
+    fn test_hotp_resync(counter: u64, codes: Vec<&str>, expected: Option<u64>) {
+        let mut hotp = rfc_4226_hotp(counter);
+        let codes: Vec<String> = codes.into_iter().map(String::from).collect();
+        let result = hotp.resync(&codes);
+        match expected {
+            Some(expected) => {
+                assert_eq!(result.unwrap(), expected);
+                assert_eq!(hotp.next_code().unwrap(), RFC_4226_CODES[expected as usize]);
+            }
+            None => {
+                assert!(matches!(result, Err(LockboxError::HotpResync(_))));
+                assert_eq!(hotp.counter, counter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_stored_hotp() {
+        let mut hotp: Hotp =
+            serde_json::from_str(r#"{"secret":"JBSWY3DPEHPK3PXP","digits":10,"counter":5}"#)
+                .unwrap();
+        for result in [
+            hotp.next_code().map(|_| ()),
+            hotp.resync(&["123456".to_string(), "654321".to_string()])
+                .map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(LockboxError::InvalidOtpSecret(message)) if message == "expected 6 to 8 digits, got 10"
+            ));
+        }
+        assert_eq!(hotp.counter, 5);
+    }
+}
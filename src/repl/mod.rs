@@ -2,14 +2,17 @@ use crate::{
     cli::{
         args::{get_password_store_path, Length, DEFAULT_PASSWORD_FILENAME},
         commands::{
-            add_password, copy_password, generate_password, list_passwords, remove_password,
-            show_password, update_master_password,
+            add_password, copy_password, edit_password, generate_password, list_passwords,
+            remove_password, show_password, show_totp, update_master_password, NewPassword,
         },
         io::{
             bold, colorize, print, read_hidden_input, read_hidden_input_with_confirmation,
             read_terminal_input, MessageType, PromptPassword,
         },
     },
+    crypto::Cipher,
+    error::{LockboxError, Result},
+    pass::{EntryChanges, EntryDetails, EntryRef, OnDuplicate},
     store::PasswordStore,
 };
 use passwords::PasswordGenerator;
@@ -17,12 +20,15 @@ use std::{
     io::{BufRead, Write},
     path::PathBuf,
 };
+use uuid::Uuid;
 
 pub fn repl<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
     file_name: String,
+    keyfile: Option<PathBuf>,
+    cipher: Cipher,
 ) {
     print(writer, &bold("Welcome to L🦀CKBOX!\n"), None);
     let file_path =
@@ -32,7 +38,14 @@ pub fn repl<R: BufRead, W: Write>(
     } else {
         read_hidden_input("master password", prompt_password)
     };
-    let password_store = match PasswordStore::new(file_path, master) {
+    let master = match master {
+        Ok(master) => master,
+        Err(err) => {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            return;
+        }
+    };
+    let password_store = match PasswordStore::with_cipher(file_path, master, keyfile, cipher) {
         Ok(password_store) => password_store,
         Err(err) => {
             writeln!(writer, "{}", err).unwrap_or_else(|_| println!("{}", err));
@@ -54,9 +67,20 @@ pub fn run_repl<R: BufRead, W: Write>(
             &format!("Failed to load password store: {err}"),
             Some(MessageType::Error),
         );
-        let master = read_hidden_input("master password", prompt_password);
-        password_store.update_master(master);
+        // Only a wrong master password can be fixed by typing it again
+        if !matches!(err, LockboxError::WrongPassword) {
+            return;
+        }
+        match read_hidden_input("master password", prompt_password) {
+            Ok(master) => password_store.update_master(master),
+            Err(err) => {
+                print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+                return;
+            }
+        };
     }
+    // Other processes can write to the store while the REPL waits for input
+    password_store.release_lock();
     loop {
         let message = [
             format!(
@@ -95,30 +119,60 @@ pub fn run_repl<R: BufRead, W: Write>(
                 colorize(&bold("copy"), MessageType::Success)
             ),
             format!(
-                "[{}] {}",
+                "[{}] {} password",
                 colorize(&bold("8"), MessageType::Success),
+                colorize(&bold("edit"), MessageType::Success)
+            ),
+            format!(
+                "[{}] {} code",
+                colorize(&bold("9"), MessageType::Success),
+                colorize(&bold("totp"), MessageType::Success)
+            ),
+            format!(
+                "[{}] {}",
+                colorize(&bold("10"), MessageType::Success),
                 colorize(&bold("exit"), MessageType::Success)
             ),
         ];
 
         let message = message.join(" ");
-        writeln!(writer, "\nEnter {message}").unwrap();
-        let input = read_terminal_input(reader, writer, None);
-        match input.as_str() {
+        print(writer, &format!("\nEnter {message}"), None);
+        let input = match read_terminal_input(reader, writer, None) {
+            Ok(input) => input,
+            Err(err) => {
+                print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+                break;
+            }
+        };
+        let result = match input.as_str() {
             "1" | "add" | "a" => {
                 handle_add_password(reader, writer, prompt_password, &mut password_store)
             }
-            "2" | "generate" | "g" => handle_generate_password(writer),
-            "3" | "list" | "l" => handle_list_passwords(writer, &mut password_store),
+            "2" | "generate" | "g" => {
+                handle_generate_password(writer);
+                Ok(())
+            }
+            "3" | "list" | "l" => {
+                handle_list_passwords(writer, &mut password_store);
+                Ok(())
+            }
             "4" | "remove" | "r" => handle_remove_password(reader, writer, &mut password_store),
             "5" | "show" | "s" => handle_show_password(reader, writer, &mut password_store),
             "6" | "update" | "u" => {
                 handle_update_master_password(writer, prompt_password, &mut password_store)
             }
-            "7" | "copy" | "c" => {
-                handle_copy_password(reader, writer, &mut password_store);
+            "7" | "copy" | "c" => handle_copy_password(reader, writer, &mut password_store),
+            "8" | "edit" | "e" => {
+                handle_edit_password(reader, writer, prompt_password, &mut password_store)
             }
+            "9" | "totp" | "t" => handle_show_totp(reader, writer, &mut password_store),
             _ => break,
+        };
+        password_store.release_lock();
+        // Input that can't be read now won't be readable on the next prompt either
+        if let Err(err) = result {
+            print(writer, &format!("Error: {err}"), Some(MessageType::Error));
+            break;
         }
     }
 }
@@ -128,7 +182,7 @@ fn handle_add_password<R: BufRead, W: Write>(
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
     password_store: &mut PasswordStore,
-) {
+) -> Result<()> {
     let message = [
         format!(
             "[{}] {} random password",
@@ -147,16 +201,16 @@ fn handle_add_password<R: BufRead, W: Write>(
         ),
     ];
     let message = message.join(" ");
-    writeln!(writer, "{}", message).unwrap();
-    let input = read_terminal_input(reader, writer, None);
+    writeln!(writer, "{}", message)?;
+    let input = read_terminal_input(reader, writer, None)?;
     let generate = match input.as_str() {
         "1" | "generate" | "g" | "random" | "r" => true,
         "2" | "enter" | "e" => false,
-        _ => return,
+        _ => return Ok(()),
     };
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
+    let service = read_terminal_input(reader, writer, Some("Please enter the service name"))?;
     let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
+        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"))?;
     let username = Option::from(username).filter(|s| !s.is_empty());
     let password_generator = PasswordGenerator::new()
         .length(Length::Sixteen.get_val())
@@ -165,23 +219,66 @@ fn handle_add_password<R: BufRead, W: Write>(
         .numbers(true)
         .symbols(false)
         .strict(true);
-    match add_password(
-        writer,
-        prompt_password,
-        password_store,
-        service,
-        username,
-        None,
-        generate,
-        password_generator,
-    ) {
-        Ok(_) => print(
+    let mut on_duplicate = OnDuplicate::Fail;
+    loop {
+        match add_password(
             writer,
-            "Password added successfully",
-            Some(MessageType::Success),
+            prompt_password,
+            password_store,
+            service.clone(),
+            username.clone(),
+            None,
+            generate,
+            password_generator.clone(),
+            EntryDetails::default(),
+            on_duplicate,
+        ) {
+            Ok(_) => print(
+                writer,
+                "Password added successfully",
+                Some(MessageType::Success),
+            ),
+            Err(err @ LockboxError::DuplicateEntry { .. }) if on_duplicate == OnDuplicate::Fail => {
+                print(writer, &err.to_string(), Some(MessageType::Warning));
+                if let Some(choice) = read_duplicate_choice(reader, writer)? {
+                    on_duplicate = choice;
+                    continue;
+                }
+            }
+            Err(err) => print(writer, &format!("Error: {err}"), Some(MessageType::Error)),
+        };
+        return Ok(());
+    }
+}
+
+fn read_duplicate_choice<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<Option<OnDuplicate>> {
+    let message = [
+        format!(
+            "[{}] {} it",
+            colorize(&bold("1"), MessageType::Success),
+            colorize(&bold("overwrite"), MessageType::Success)
         ),
-        Err(err) => print(writer, &format!("Error: {err}"), Some(MessageType::Error)),
-    };
+        format!(
+            "[{}] {}",
+            colorize(&bold("2"), MessageType::Success),
+            colorize(&bold("keep both"), MessageType::Success)
+        ),
+        format!(
+            "[{}] {}",
+            colorize(&bold("3"), MessageType::Success),
+            colorize(&bold("cancel"), MessageType::Success)
+        ),
+    ];
+    writeln!(writer, "{}", message.join(" "))?;
+    let input = read_terminal_input(reader, writer, None)?;
+    Ok(match input.as_str() {
+        "1" | "overwrite" | "o" => Some(OnDuplicate::Overwrite),
+        "2" | "keep both" | "k" => Some(OnDuplicate::KeepBoth),
+        _ => None,
+    })
 }
 
 fn handle_generate_password<W: Write>(writer: &mut W) {
@@ -192,7 +289,7 @@ fn handle_generate_password<W: Write>(writer: &mut W) {
 }
 
 fn handle_list_passwords<W: Write>(writer: &mut W, password_store: &mut PasswordStore) {
-    list_passwords(writer, password_store, true).unwrap_or_else(|err| {
+    list_passwords(writer, password_store, true, None).unwrap_or_else(|err| {
         print(
             writer,
             &format!("Failed to load passwords to store: {err}"),
@@ -205,41 +302,54 @@ fn handle_remove_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     password_store: &mut PasswordStore,
-) {
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
-    let username = Option::from(username).filter(|s| !s.is_empty());
-    remove_password(writer, password_store, service, username).unwrap_or_else(|err| {
+) -> Result<()> {
+    let entry = read_entry_ref(reader, writer)?;
+    remove_password(writer, password_store, entry).unwrap_or_else(|err| {
         print(
             writer,
             &format!("Failed to remove password: {err}"),
             Some(MessageType::Error),
         )
-    })
+    });
+    Ok(())
 }
 
 fn handle_show_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     password_store: &mut PasswordStore,
-) {
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
-    let username = Option::from(username).filter(|s| !s.is_empty());
-    if show_password(writer, password_store, service, username).is_err() {
+) -> Result<()> {
+    let entry = read_entry_ref(reader, writer)?;
+    if show_password(writer, password_store, entry, true).is_err() {
         print(writer, "Password not found", None);
     };
+    Ok(())
+}
+
+/// Asks for the service name and username of an entry. Its id can be entered in place of the
+/// service name instead.
+fn read_entry_ref<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<EntryRef> {
+    let service = read_terminal_input(
+        reader,
+        writer,
+        Some("Please enter the service name (or the id)"),
+    )?;
+    if let Ok(id) = Uuid::parse_str(&service) {
+        return Ok(EntryRef::Id(id));
+    }
+    let username =
+        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"))?;
+    let username = Option::from(username).filter(|s| !s.is_empty());
+    Ok(EntryRef::name(service, username))
 }
 
 fn handle_update_master_password<W: Write>(
     writer: &mut W,
     prompt_password: &dyn PromptPassword,
     password_store: &mut PasswordStore,
-) {
+) -> Result<()> {
     let new_master_password =
-        read_hidden_input_with_confirmation(writer, "new password", prompt_password);
+        read_hidden_input_with_confirmation(writer, "new password", prompt_password)?;
     update_master_password(writer, new_master_password, password_store).unwrap_or_else(|err| {
         print(
             writer,
@@ -247,20 +357,106 @@ fn handle_update_master_password<W: Write>(
             Some(MessageType::Error),
         );
     });
+    Ok(())
 }
 
 fn handle_copy_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     password_store: &mut PasswordStore,
-) {
-    let service = read_terminal_input(reader, writer, Some("Please enter the service name"));
-    let username =
-        read_terminal_input(reader, writer, Some("Please enter the username (Optional)"));
-    let username = Option::from(username).filter(|s| !s.is_empty());
-    if copy_password(writer, password_store, service, username).is_err() {
+) -> Result<()> {
+    let entry = read_entry_ref(reader, writer)?;
+    if copy_password(writer, password_store, entry).is_err() {
         print(writer, "Password not found", None);
     }
+    Ok(())
+}
+
+fn handle_edit_password<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt_password: &dyn PromptPassword,
+    password_store: &mut PasswordStore,
+) -> Result<()> {
+    let entry = read_entry_ref(reader, writer)?;
+    let message = [
+        format!(
+            "[{}] {} a new password",
+            colorize(&bold("1"), MessageType::Success),
+            colorize(&bold("generate"), MessageType::Success)
+        ),
+        format!(
+            "[{}] {} a new password",
+            colorize(&bold("2"), MessageType::Success),
+            colorize(&bold("enter"), MessageType::Success)
+        ),
+        format!(
+            "[{}] {} the password",
+            colorize(&bold("3"), MessageType::Success),
+            colorize(&bold("keep"), MessageType::Success)
+        ),
+    ];
+    writeln!(writer, "{}", message.join(" "))?;
+    let new_password = match read_terminal_input(reader, writer, None)?.as_str() {
+        "1" | "generate" | "g" => NewPassword::Generate(
+            PasswordGenerator::new()
+                .length(Length::Sixteen.get_val())
+                .lowercase_letters(true)
+                .uppercase_letters(true)
+                .numbers(true)
+                .symbols(false)
+                .strict(true),
+        ),
+        "2" | "enter" | "e" => NewPassword::Prompt,
+        _ => NewPassword::Keep,
+    };
+    let mut read_change = |prompt: &str| -> Result<Option<String>> {
+        let input = read_terminal_input(reader, writer, Some(prompt))?;
+        Ok(Some(input).filter(|input| !input.is_empty()))
+    };
+    let changes = EntryChanges {
+        password: None,
+        service: read_change("New service name (leave empty to keep it)")?,
+        username: read_change("New username (leave empty to keep it)")?,
+        urls: read_change("New URL (leave empty to keep the URLs)")?.map(|url| vec![url]),
+        notes: read_change("New notes (leave empty to keep them)")?,
+        tags: None,
+        fields: Vec::new(),
+        totp: None,
+        hotp: None,
+    };
+    edit_password(
+        writer,
+        prompt_password,
+        password_store,
+        entry,
+        new_password,
+        changes,
+    )
+    .unwrap_or_else(|err| {
+        print(
+            writer,
+            &format!("Failed to edit password: {err}"),
+            Some(MessageType::Error),
+        )
+    });
+    Ok(())
+}
+
+fn handle_show_totp<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    password_store: &mut PasswordStore,
+) -> Result<()> {
+    let entry = read_entry_ref(reader, writer)?;
+    show_totp(writer, password_store, entry).unwrap_or_else(|err| {
+        print(
+            writer,
+            &format!("Failed to get TOTP code: {err}"),
+            Some(MessageType::Error),
+        )
+    });
+    Ok(())
 }
 
 #[cfg(test)]
@@ -295,6 +491,8 @@ mod tests {
             &mut output,
             &mock_prompt_password,
             temp_file_name,
+            None,
+            Cipher::default(),
         );
 
         let output_str = String::from_utf8(output).unwrap();
@@ -306,6 +504,8 @@ mod tests {
             "remove",
             "show",
             "update master",
+            "edit",
+            "totp",
             "exit",
         ];
         for operation in operations {
@@ -313,6 +513,47 @@ mod tests {
         }
     }
 
+    #[rstest(
+        input,
+        expected,
+        case(
+            b"67e55044-10b1-426f-9247-bb680e5fe0c8\n" as &[u8],
+            EntryRef::Id(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap())
+        ),
+        case(b"github\noctocat\n" as &[u8], EntryRef::name("github".to_string(), Some("octocat".to_string()))),
+        case(b"github\n\n" as &[u8], EntryRef::name("github".to_string(), None))
+    )]
+    fn test_read_entry_ref(input: &[u8], expected: EntryRef) {
+        let mut input = input;
+        let entry = read_entry_ref(&mut input, &mut Vec::new()).unwrap();
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn test_repl_without_tty() {
+        let mut output = Vec::new();
+        let mut mock_prompt_password = MockPromptPassword::new();
+        mock_prompt_password
+            .expect_prompt_password()
+            .times(1)
+            .returning(|_| Err(std::io::Error::other("No such device or address")));
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_file_name = temp_file.path().to_str().unwrap().to_string();
+
+        repl(
+            &mut (b"" as &[u8]),
+            &mut output,
+            &mock_prompt_password,
+            temp_file_name,
+            None,
+            Cipher::default(),
+        );
+
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Error: Failed to read master password"));
+        assert!(!output_str.contains("[1]"));
+    }
+
     #[rstest(
         input,
         expected_output,
@@ -322,6 +563,18 @@ mod tests {
                 "generate", "enter", "cancel", "Please enter the service name", "Please enter the username (Optional)", "Password added successfully", ">>",
             ],
         ),
+        case(
+            b"add\n1\nservice\nusername\n2\nexit\n" as &[u8],
+            vec!["A password for service (username) already exists", "overwrite", "keep both", "Password stored as service (2)", "Password added successfully"],
+        ),
+        case(
+            b"add\n1\nservice\nusername\n3\nexit\n" as &[u8],
+            vec!["A password for service (username) already exists", "overwrite", "keep both"],
+        ),
+        case(
+            b"edit\nservice\nusername\n3\nrenamed\n\n\nshared account\nlist\nexit\n" as &[u8],
+            vec!["New service name", "New username", "New URL", "New notes", "Password updated", "renamed"],
+        ),
         case(
             b"list\nexit\n" as &[u8],
             vec!["Service:", "service",  "Username:", "username", "Password:", "password"]
@@ -338,10 +591,14 @@ mod tests {
             b"show\nservice\nusername\nexit\n" as &[u8],
             vec!["Password:", "password"],
         ),
+        case(
+            b"totp\nservice\nusername\nexit\n" as &[u8],
+            vec!["Failed to get TOTP code: This password has no TOTP secret"],
+        ),
     )]
     fn test_run_repl(input: &[u8], expected_output: Vec<&str>) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -353,6 +610,8 @@ mod tests {
             Some("password".to_string()),
             false,
             PasswordGenerator::default(),
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         )
         .unwrap();
         let mut input = input;
@@ -373,6 +632,8 @@ mod tests {
             "remove",
             "show",
             "update master",
+            "edit",
+            "totp",
             "exit",
         ];
         for operation in operations {
@@ -386,7 +647,7 @@ mod tests {
     #[test]
     fn test_handle_add_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut input = b"1\ntest_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
         let mock_prompt_password = &MockPromptPassword::new();
@@ -396,7 +657,8 @@ mod tests {
             &mut output,
             mock_prompt_password,
             &mut password_store,
-        );
+        )
+        .unwrap();
 
         let output_str = String::from_utf8(output).unwrap();
         let operations = ["random password", "enter", "cancel"];
@@ -421,7 +683,7 @@ mod tests {
     #[test]
     fn test_handle_list_passwords() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -433,6 +695,8 @@ mod tests {
             Some("password".to_string()),
             false,
             PasswordGenerator::default(),
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         )
         .unwrap();
         let mut output = Vec::new();
@@ -448,7 +712,7 @@ mod tests {
     #[test]
     fn test_handle_remove_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -460,18 +724,20 @@ mod tests {
             Some("password".to_string()),
             false,
             PasswordGenerator::default(),
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         )
         .unwrap();
 
         let mut input = b"test_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
-        handle_remove_password(&mut input, &mut output, &mut password_store);
+        handle_remove_password(&mut input, &mut output, &mut password_store).unwrap();
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password not found"));
 
         input = b"service\nusername\n" as &[u8];
         output = Vec::new();
-        handle_remove_password(&mut input, &mut output, &mut password_store);
+        handle_remove_password(&mut input, &mut output, &mut password_store).unwrap();
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password deleted"));
     }
@@ -479,7 +745,7 @@ mod tests {
     #[test]
     fn test_handle_show_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         add_password(
@@ -491,18 +757,20 @@ mod tests {
             Some("password".to_string()),
             false,
             PasswordGenerator::default(),
+            EntryDetails::default(),
+            OnDuplicate::Fail,
         )
         .unwrap();
 
         let mut input = b"test_service\ntest_username\n" as &[u8];
         let mut output = Vec::new();
-        handle_show_password(&mut input, &mut output, &mut password_store);
+        handle_show_password(&mut input, &mut output, &mut password_store).unwrap();
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("Password not found"));
 
         input = b"service\nusername\n" as &[u8];
         output = Vec::new();
-        handle_show_password(&mut input, &mut output, &mut password_store);
+        handle_show_password(&mut input, &mut output, &mut password_store).unwrap();
         let output_str = String::from_utf8(output).unwrap();
         assert!(output_str.contains("password"));
     }
@@ -510,13 +778,14 @@ mod tests {
     #[test]
     fn test_handle_update_master_password() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut password_store = PasswordStore::new(temp_file, "secret".to_string()).unwrap();
+        let mut password_store = PasswordStore::new(temp_file, "secret".to_string(), None).unwrap();
         let mut writer = Vec::new();
         let mut mock_prompt_password = MockPromptPassword::new();
         mock_prompt_password
             .expect_prompt_password()
             .returning(|_| Ok("newmasterpassword".to_string()));
-        handle_update_master_password(&mut writer, &mock_prompt_password, &mut password_store);
+        handle_update_master_password(&mut writer, &mock_prompt_password, &mut password_store)
+            .unwrap();
         let output_str = String::from_utf8(writer).unwrap();
         assert!(output_str.contains(&colorize(
             "Master password updated successfully",
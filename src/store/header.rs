@@ -0,0 +1,646 @@
+use crate::{
+    crypto::{
+        decrypt, encrypt, get_random_salt, key_from_slice, sha256, Cipher, Kdf, Key, KEY_LEN,
+        PBKDF2_ITERATIONS, SALT_LEN,
+    },
+    error::{LockboxError, Result},
+};
+use std::fmt::Display;
+use zeroize::Zeroizing;
+
+pub const MAGIC: &[u8; 4] = b"LBOX";
+pub const CURRENT_VERSION: u8 = 3;
+/// From this version on, the whole header is authenticated as associated data of the encrypted
+/// passwords.
+const FIRST_AUTHENTICATED_VERSION: u8 = 3;
+
+const KDF_PBKDF2_HMAC_SHA256: u8 = 1;
+const KDF_ARGON2ID: u8 = 2;
+const CIPHER_AES_256_GCM: u8 = 1;
+const CIPHER_XCHACHA20_POLY1305: u8 = 2;
+const SLOT_PASSWORD: u8 = 1;
+const SLOT_KEYFILE: u8 = 2;
+const SLOT_PASSWORD_AND_KEYFILE: u8 = 3;
+const SLOT_RECOVERY: u8 = 4;
+
+/// The kind of secret that opens a key slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotKind {
+    Password,
+    Keyfile,
+    PasswordAndKeyfile,
+    /// A one-time recovery code, removed once it has been used.
+    Recovery,
+}
+
+impl Display for SlotKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlotKind::Password => write!(f, "password"),
+            SlotKind::Keyfile => write!(f, "keyfile"),
+            SlotKind::PasswordAndKeyfile => write!(f, "password + keyfile"),
+            SlotKind::Recovery => write!(f, "recovery code"),
+        }
+    }
+}
+
+/// The secret offered to create or open a key slot.
+#[derive(Debug, Clone, Copy)]
+pub enum SlotSecret<'a> {
+    Password(&'a [u8]),
+    Keyfile(&'a [u8]),
+    /// The keyfile contents are mixed into the key derivation, so both are needed.
+    PasswordAndKeyfile(&'a [u8], &'a [u8]),
+    Recovery(&'a [u8]),
+}
+
+impl SlotSecret<'_> {
+    pub fn kind(&self) -> SlotKind {
+        match self {
+            SlotSecret::Password(_) => SlotKind::Password,
+            SlotSecret::Keyfile(_) => SlotKind::Keyfile,
+            SlotSecret::PasswordAndKeyfile(..) => SlotKind::PasswordAndKeyfile,
+            SlotSecret::Recovery(_) => SlotKind::Recovery,
+        }
+    }
+
+    fn key_material(&self) -> Zeroizing<Vec<u8>> {
+        match self {
+            SlotSecret::Password(secret)
+            | SlotSecret::Keyfile(secret)
+            | SlotSecret::Recovery(secret) => Zeroizing::new(secret.to_vec()),
+            SlotSecret::PasswordAndKeyfile(password, keyfile) => {
+                let mut material = Zeroizing::new(password.to_vec());
+                material.extend(sha256(keyfile));
+                material
+            }
+        }
+    }
+}
+
+const KEYFILE_CHECK_PREFIX: &[u8] = b"lockbox keyfile check\0";
+
+/// The longest key slot label, in bytes, that fits its one byte length prefix.
+pub const MAX_LABEL_LEN: usize = u8::MAX as usize;
+
+/// A copy of the store's data key, encrypted with a key derived from one unlock secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySlot {
+    pub kind: SlotKind,
+    pub label: String,
+    pub kdf: Kdf,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub wrapped_key: Vec<u8>,
+    /// Key derived from the keyfile alone for password + keyfile slots, to tell a wrong keyfile
+    /// apart from a wrong password. It goes through the slot's KDF, so guessing the keyfile
+    /// from it costs as much as guessing a master password.
+    pub keyfile_check: Option<Vec<u8>>,
+}
+
+impl KeySlot {
+    pub fn new(
+        label: String,
+        kdf: Kdf,
+        cipher: Cipher,
+        secret: SlotSecret,
+        data_key: &[u8; KEY_LEN],
+    ) -> Result<Self> {
+        if label.len() > MAX_LABEL_LEN {
+            return Err(LockboxError::KeySlot(format!(
+                "Key slot labels can be at most {MAX_LABEL_LEN} bytes long"
+            )));
+        }
+        let salt = get_random_salt()?.to_vec();
+        let slot_key = kdf.derive_key(&secret.key_material(), &salt)?;
+        let nonce = cipher.generate_nonce()?;
+        let wrapped_key = encrypt(cipher, &slot_key, &nonce, data_key, &[])?;
+        let keyfile_check = match secret {
+            SlotSecret::PasswordAndKeyfile(_, keyfile) => Some(keyfile_check(kdf, &salt, keyfile)?),
+            _ => None,
+        };
+        Ok(Self {
+            kind: secret.kind(),
+            label,
+            kdf,
+            salt,
+            nonce,
+            wrapped_key,
+            keyfile_check,
+        })
+    }
+
+    /// Whether `keyfile` is the one this slot was created with. Always true for slots that
+    /// don't mix in a keyfile.
+    pub fn keyfile_matches(&self, keyfile: &[u8]) -> Result<bool> {
+        match self.keyfile_check.as_ref() {
+            Some(check) => Ok(*check == keyfile_check(self.kdf, &self.salt, keyfile)?),
+            None => Ok(true),
+        }
+    }
+
+    /// Returns the data key, or `None` if the secret doesn't open this slot.
+    pub fn unwrap_key(&self, cipher: Cipher, secret: SlotSecret) -> Result<Option<Key>> {
+        if secret.kind() != self.kind {
+            return Ok(None);
+        }
+        let slot_key = self.kdf.derive_key(&secret.key_material(), &self.salt)?;
+        match decrypt(cipher, &slot_key, &self.nonce, &self.wrapped_key, &[]) {
+            Ok(data_key) => {
+                let data_key = key_from_slice(&data_key)
+                    .ok_or_else(|| LockboxError::CorruptStore("invalid key slot".to_string()))?;
+                Ok(Some(data_key))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn keyfile_check(kdf: Kdf, salt: &[u8], keyfile: &[u8]) -> Result<Vec<u8>> {
+    // Prefixed so it never matches the material of the slot key, which has the same salt
+    let mut material = Zeroizing::new(KEYFILE_CHECK_PREFIX.to_vec());
+    material.extend(sha256(keyfile));
+    Ok(kdf.derive_key(&material, salt)?.to_vec())
+}
+
+/// Where the key that encrypts the passwords comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreKey {
+    /// Versions 0 and 1: the key is derived directly from the master password.
+    Derived { kdf: Kdf, salt: Vec<u8> },
+    /// A random data key, wrapped by one or more key slots.
+    Wrapped(Vec<KeySlot>),
+}
+
+/// Metadata stored in front of the encrypted passwords.
+///
+/// Files written before the header existed start directly with the salt and are read as
+/// version 0 (PBKDF2 with 100,000 iterations, AES-256-GCM).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreHeader {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub key: StoreKey,
+    pub nonce: Vec<u8>,
+}
+
+impl StoreHeader {
+    pub fn new(cipher: Cipher, slots: Vec<KeySlot>, nonce: Vec<u8>) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            cipher,
+            key: StoreKey::Wrapped(slots),
+            nonce,
+        }
+    }
+
+    /// Splits the store file into its header and the encrypted passwords.
+    pub fn parse(contents: &[u8]) -> Result<(StoreHeader, &[u8])> {
+        let mut reader = Reader::new(contents);
+        if !contents.starts_with(MAGIC) {
+            let salt = reader.take(SALT_LEN)?.to_vec();
+            let nonce = reader.take(Cipher::Aes256Gcm.nonce_len())?.to_vec();
+            let header = StoreHeader {
+                version: 0,
+                cipher: Cipher::Aes256Gcm,
+                key: StoreKey::Derived {
+                    kdf: Kdf::Pbkdf2 {
+                        iterations: PBKDF2_ITERATIONS,
+                    },
+                    salt,
+                },
+                nonce,
+            };
+            return Ok((header, reader.rest()));
+        }
+        reader.take(MAGIC.len())?;
+        let version = reader.u8()?;
+        let (cipher, key) = match version {
+            1 => {
+                let kdf = reader.kdf()?;
+                let salt = reader.bytes()?.to_vec();
+                let cipher = reader.cipher()?;
+                (cipher, StoreKey::Derived { kdf, salt })
+            }
+            // Version 3 has the same layout, but authenticates the header
+            2 | 3 => {
+                let cipher = reader.cipher()?;
+                let slot_count = reader.u8()?;
+                let slots = (0..slot_count)
+                    .map(|_| reader.slot(cipher))
+                    .collect::<Result<Vec<KeySlot>>>()?;
+                (cipher, StoreKey::Wrapped(slots))
+            }
+            _ => return Err(LockboxError::UnsupportedVersion(version)),
+        };
+        let nonce = reader.take(cipher.nonce_len())?.to_vec();
+        let header = StoreHeader {
+            version,
+            cipher,
+            key,
+            nonce,
+        };
+        Ok((header, reader.rest()))
+    }
+
+    /// Whether the header bytes were authenticated as associated data of the encrypted passwords.
+    pub fn is_authenticated(&self) -> bool {
+        self.version >= FIRST_AUTHENTICATED_VERSION
+    }
+
+    /// Serializes the header in the current format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let slots = match &self.key {
+            StoreKey::Wrapped(slots) => slots,
+            StoreKey::Derived { .. } => {
+                return Err(LockboxError::NotLoaded("writing it in the current format"))
+            }
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(CURRENT_VERSION);
+        write_cipher(&mut bytes, self.cipher);
+        bytes.push(u8::try_from(slots.len()).map_err(|_| {
+            LockboxError::KeySlot("The password store has too many key slots".to_string())
+        })?);
+        for slot in slots {
+            bytes.push(match slot.kind {
+                SlotKind::Password => SLOT_PASSWORD,
+                SlotKind::Keyfile => SLOT_KEYFILE,
+                SlotKind::PasswordAndKeyfile => SLOT_PASSWORD_AND_KEYFILE,
+                SlotKind::Recovery => SLOT_RECOVERY,
+            });
+            write_bytes(&mut bytes, slot.label.as_bytes())?;
+            write_kdf(&mut bytes, slot.kdf);
+            write_bytes(&mut bytes, &slot.salt)?;
+            bytes.extend(&slot.nonce);
+            write_bytes(&mut bytes, &slot.wrapped_key)?;
+            if slot.kind == SlotKind::PasswordAndKeyfile {
+                write_bytes(
+                    &mut bytes,
+                    slot.keyfile_check.as_deref().unwrap_or_default(),
+                )?;
+            }
+        }
+        bytes.extend(&self.nonce);
+        Ok(bytes)
+    }
+}
+
+/// Writes `value` after its length as one byte. Fails for values longer than 255 bytes, which
+/// would otherwise be cut off and leave a store that can't be parsed.
+pub(super) fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) -> Result<()> {
+    let len = u8::try_from(value.len()).map_err(|_| {
+        LockboxError::KeySlot(format!(
+            "Key slot field of {} bytes is too long to write",
+            value.len()
+        ))
+    })?;
+    bytes.push(len);
+    bytes.extend(value);
+    Ok(())
+}
+
+pub(super) fn write_kdf(bytes: &mut Vec<u8>, kdf: Kdf) {
+    match kdf {
+        Kdf::Pbkdf2 { iterations } => {
+            bytes.push(KDF_PBKDF2_HMAC_SHA256);
+            bytes.extend(iterations.to_le_bytes());
+        }
+        Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => {
+            bytes.push(KDF_ARGON2ID);
+            bytes.extend(memory_kib.to_le_bytes());
+            bytes.extend(iterations.to_le_bytes());
+            bytes.extend(parallelism.to_le_bytes());
+        }
+    }
+}
+
+pub(super) fn write_cipher(bytes: &mut Vec<u8>, cipher: Cipher) {
+    match cipher {
+        Cipher::Aes256Gcm => bytes.push(CIPHER_AES_256_GCM),
+        Cipher::XChaCha20Poly1305 => bytes.push(CIPHER_XCHACHA20_POLY1305),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position + len;
+        let slice = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| LockboxError::CorruptStore("unexpected end of header".to_string()))?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        let mut le_bytes = [0u8; 4];
+        le_bytes.copy_from_slice(bytes);
+        Ok(u32::from_le_bytes(le_bytes))
+    }
+
+    /// Reads a length-prefixed byte string.
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    fn kdf(&mut self) -> Result<Kdf> {
+        match self.u8()? {
+            KDF_PBKDF2_HMAC_SHA256 => Ok(Kdf::Pbkdf2 {
+                iterations: self.u32()?,
+            }),
+            KDF_ARGON2ID => Ok(Kdf::Argon2id {
+                memory_kib: self.u32()?,
+                iterations: self.u32()?,
+                parallelism: self.u32()?,
+            }),
+            id => Err(LockboxError::CorruptStore(format!(
+                "unknown key derivation function id {id}"
+            ))),
+        }
+    }
+
+    fn cipher(&mut self) -> Result<Cipher> {
+        match self.u8()? {
+            CIPHER_AES_256_GCM => Ok(Cipher::Aes256Gcm),
+            CIPHER_XCHACHA20_POLY1305 => Ok(Cipher::XChaCha20Poly1305),
+            id => Err(LockboxError::CorruptStore(format!(
+                "unknown cipher id {id}"
+            ))),
+        }
+    }
+
+    fn slot(&mut self, cipher: Cipher) -> Result<KeySlot> {
+        let kind = match self.u8()? {
+            SLOT_PASSWORD => SlotKind::Password,
+            SLOT_KEYFILE => SlotKind::Keyfile,
+            SLOT_PASSWORD_AND_KEYFILE => SlotKind::PasswordAndKeyfile,
+            SLOT_RECOVERY => SlotKind::Recovery,
+            id => {
+                return Err(LockboxError::CorruptStore(format!(
+                    "unknown key slot kind {id}"
+                )))
+            }
+        };
+        let label = String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| LockboxError::CorruptStore("invalid key slot label".to_string()))?;
+        let kdf = self.kdf()?;
+        let salt = self.bytes()?.to_vec();
+        let nonce = self.take(cipher.nonce_len())?.to_vec();
+        let wrapped_key = self.bytes()?.to_vec();
+        let keyfile_check = match kind {
+            SlotKind::PasswordAndKeyfile => Some(self.bytes()?.to_vec()),
+            _ => None,
+        };
+        Ok(KeySlot {
+            kind,
+            label,
+            kdf,
+            salt,
+            nonce,
+            wrapped_key,
+            keyfile_check,
+        })
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_key;
+    use rstest::rstest;
+
+    const TEST_KDF: Kdf = Kdf::Argon2id {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn test_slot(kind: SlotKind, kdf: Kdf) -> KeySlot {
+        KeySlot {
+            kind,
+            label: "label".to_string(),
+            kdf,
+            salt: vec![3u8; SALT_LEN],
+            nonce: vec![4u8; 12],
+            wrapped_key: vec![5u8; 48],
+            keyfile_check: (kind == SlotKind::PasswordAndKeyfile).then(|| vec![6u8; 32]),
+        }
+    }
+
+    #[rstest(
+        cipher,
+        slots,
+        case(
+            Cipher::Aes256Gcm,
+            vec![test_slot(SlotKind::Password, Kdf::Pbkdf2 { iterations: 1234 })]
+        ),
+        case(
+            Cipher::Aes256Gcm,
+            vec![
+                test_slot(SlotKind::Password, Kdf::default()),
+                test_slot(SlotKind::Keyfile, TEST_KDF),
+                test_slot(SlotKind::PasswordAndKeyfile, TEST_KDF),
+                test_slot(SlotKind::Recovery, TEST_KDF),
+            ]
+        ),
+        case(
+            Cipher::XChaCha20Poly1305,
+            vec![
+                test_slot(SlotKind::Password, Kdf::default()),
+                test_slot(SlotKind::Keyfile, TEST_KDF),
+            ]
+        )
+    )]
+    fn test_header_round_trip(cipher: Cipher, mut slots: Vec<KeySlot>) {
+        for slot in slots.iter_mut() {
+            slot.nonce.resize(cipher.nonce_len(), 4);
+        }
+        let header = StoreHeader::new(cipher, slots, vec![9u8; cipher.nonce_len()]);
+        let mut contents = header.to_bytes().unwrap();
+        contents.extend(b"ciphertext");
+        let (parsed, rest) = StoreHeader::parse(&contents).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(rest, b"ciphertext");
+    }
+
+    #[test]
+    fn test_parse_headerless_file_as_v0() {
+        let mut contents = vec![1u8; SALT_LEN];
+        contents.extend([2u8; 12]);
+        contents.extend(b"ciphertext");
+        let (header, rest) = StoreHeader::parse(&contents).unwrap();
+        assert_eq!(header.version, 0);
+        assert_eq!(
+            header.key,
+            StoreKey::Derived {
+                kdf: Kdf::Pbkdf2 {
+                    iterations: PBKDF2_ITERATIONS
+                },
+                salt: vec![1u8; SALT_LEN],
+            }
+        );
+        assert_eq!(header.cipher, Cipher::Aes256Gcm);
+        assert_eq!(header.nonce, vec![2u8; 12]);
+        assert_eq!(rest, b"ciphertext");
+    }
+
+    #[test]
+    fn test_parse_v1() {
+        let mut contents = MAGIC.to_vec();
+        contents.push(1);
+        write_kdf(&mut contents, TEST_KDF);
+        write_bytes(&mut contents, &[1u8; SALT_LEN]).unwrap();
+        write_cipher(&mut contents, Cipher::Aes256Gcm);
+        contents.extend([2u8; 12]);
+        contents.extend(b"ciphertext");
+        let (header, rest) = StoreHeader::parse(&contents).unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(
+            header.key,
+            StoreKey::Derived {
+                kdf: TEST_KDF,
+                salt: vec![1u8; SALT_LEN],
+            }
+        );
+        assert_eq!(header.nonce, vec![2u8; 12]);
+        assert_eq!(rest, b"ciphertext");
+    }
+
+    #[test]
+    fn test_derived_header_is_not_written() {
+        let header = StoreHeader {
+            version: 1,
+            cipher: Cipher::Aes256Gcm,
+            key: StoreKey::Derived {
+                kdf: TEST_KDF,
+                salt: vec![1u8; SALT_LEN],
+            },
+            nonce: vec![2u8; 12],
+        };
+        assert!(header.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_oversized_header_is_not_written() {
+        let mut slot = test_slot(SlotKind::Password, TEST_KDF);
+        slot.label = "a".repeat(MAX_LABEL_LEN + 1);
+        let header = StoreHeader::new(Cipher::Aes256Gcm, vec![slot], vec![9u8; 12]);
+        assert!(matches!(header.to_bytes(), Err(LockboxError::KeySlot(_))));
+
+        let slots = vec![test_slot(SlotKind::Password, TEST_KDF); u8::MAX as usize + 1];
+        let header = StoreHeader::new(Cipher::Aes256Gcm, slots, vec![9u8; 12]);
+        assert!(matches!(header.to_bytes(), Err(LockboxError::KeySlot(_))));
+    }
+
+    #[test]
+    fn test_parse_unsupported_version() {
+        let mut contents = MAGIC.to_vec();
+        contents.push(CURRENT_VERSION + 1);
+        let err = StoreHeader::parse(&contents).unwrap_err();
+        assert!(
+            matches!(err, LockboxError::UnsupportedVersion(version) if version == CURRENT_VERSION + 1)
+        );
+        assert!(err
+            .to_string()
+            .contains("Unsupported password store format version"));
+    }
+
+    #[test]
+    fn test_parse_truncated_header() {
+        let header = StoreHeader::new(
+            Cipher::Aes256Gcm,
+            vec![test_slot(SlotKind::Password, TEST_KDF)],
+            vec![9u8; 12],
+        );
+        let contents = header.to_bytes().unwrap();
+        let err = StoreHeader::parse(&contents[..contents.len() - 1]).unwrap_err();
+        assert!(matches!(err, LockboxError::CorruptStore(_)));
+        assert!(err.to_string().contains("unexpected end of header"));
+    }
+
+    #[test]
+    fn test_key_slot_unwrap() {
+        let data_key = generate_key().unwrap();
+        let slot = KeySlot::new(
+            "master password".to_string(),
+            TEST_KDF,
+            Cipher::Aes256Gcm,
+            SlotSecret::Password(b"secret"),
+            &data_key,
+        )
+        .unwrap();
+        assert_eq!(
+            slot.unwrap_key(Cipher::Aes256Gcm, SlotSecret::Password(b"secret"))
+                .unwrap(),
+            Some(data_key)
+        );
+        assert_eq!(
+            slot.unwrap_key(Cipher::Aes256Gcm, SlotSecret::Password(b"wrong"))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            slot.unwrap_key(Cipher::Aes256Gcm, SlotSecret::Keyfile(b"secret"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_key_slot_with_keyfile() {
+        let data_key = generate_key().unwrap();
+        let slot = KeySlot::new(
+            "master password".to_string(),
+            TEST_KDF,
+            Cipher::Aes256Gcm,
+            SlotSecret::PasswordAndKeyfile(b"secret", b"keyfile"),
+            &data_key,
+        )
+        .unwrap();
+        assert!(slot.keyfile_matches(b"keyfile").unwrap());
+        assert!(!slot.keyfile_matches(b"other keyfile").unwrap());
+        // Only a key derivation tells whether a keyfile matches, not a plain salted hash
+        let mut salted = slot.salt.clone();
+        salted.extend(sha256(b"keyfile"));
+        assert_ne!(slot.keyfile_check.as_deref(), Some(&sha256(&salted)[..]));
+        let unwrap = |password: &[u8], keyfile: &[u8]| {
+            slot.unwrap_key(
+                Cipher::Aes256Gcm,
+                SlotSecret::PasswordAndKeyfile(password, keyfile),
+            )
+            .unwrap()
+        };
+        assert_eq!(unwrap(b"secret", b"keyfile"), Some(data_key));
+        assert_eq!(unwrap(b"secret", b"other keyfile"), None);
+        assert_eq!(unwrap(b"wrong", b"keyfile"), None);
+        assert_eq!(
+            slot.unwrap_key(Cipher::Aes256Gcm, SlotSecret::Password(b"secret"))
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::{LockboxError, Result};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for another process to release the store before giving up.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An exclusive advisory lock on a password store, held on a `<store>.lock` file next to it
+/// until dropped. The store file itself can't be locked because every write replaces it with
+/// a new file. The lock file holds the pid of the process that owns the lock.
+#[derive(Debug)]
+pub struct StoreLock {
+    _file: File,
+}
+
+impl StoreLock {
+    /// Takes the lock on the store at `store_path`, waiting up to `timeout` for another process
+    /// to release it.
+    pub fn acquire(store_path: &Path, timeout: Duration) -> Result<Self> {
+        let path = lock_path(store_path);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let started = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                    thread::sleep(RETRY_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(LockboxError::Locked {
+                        pid: read_pid(&path),
+                    })
+                }
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(Self { _file: file })
+    }
+}
+
+pub fn lock_path(store_path: &Path) -> PathBuf {
+    let mut file_name = store_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    store_path.with_file_name(file_name)
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    let mut contents = String::new();
+    fs::File::open(path)
+        .ok()?
+        .read_to_string(&mut contents)
+        .ok()?;
+    contents.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp_dir = tempdir().unwrap();
+        let store_path = temp_dir.path().join("store");
+        let lock = StoreLock::acquire(&store_path, Duration::ZERO).unwrap();
+        assert_eq!(
+            fs::read_to_string(lock_path(&store_path)).unwrap(),
+            std::process::id().to_string()
+        );
+
+        let err = StoreLock::acquire(&store_path, RETRY_INTERVAL * 2).unwrap_err();
+        assert!(
+            matches!(err, LockboxError::Locked { pid: Some(pid) } if pid == std::process::id())
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Password store is locked by pid {}", std::process::id())
+        );
+
+        drop(lock);
+        assert!(StoreLock::acquire(&store_path, Duration::ZERO).is_ok());
+    }
+}
@@ -1,96 +1,471 @@
+pub mod header;
+pub mod lock;
+
 use crate::cli::io::{print, MessageType};
-use crate::pass::PasswordEntry;
+use crate::error::{LockboxError, Result};
+use crate::pass::{EntryChanges, EntryRef, Kind, OnDuplicate, PasswordEntry};
 use crate::{
-    crypto::{encrypt_contents, get_cipher, get_random_salt},
+    crypto::{
+        combine_shares, decrypt, encrypt, generate_id, generate_key, generate_recovery_code,
+        key_from_slice, memory::Secret, normalize_recovery_code, sha256, split_secret, Cipher, Kdf,
+        Key, KEY_LEN,
+    },
     pass::Passwords,
 };
-use aes_gcm::aead::Aead;
+use header::{KeySlot, SlotKind, SlotSecret, StoreHeader, StoreKey};
+use lock::{StoreLock, DEFAULT_LOCK_TIMEOUT};
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use zeroize::Zeroizing;
 
 const EMPTY_PASSWORDS: &str = "[]";
+/// How many encrypted copies of previous versions of the store are kept next to it.
+pub const BACKUP_COUNT: usize = 5;
+
+/// What the data key is recovered with when loading the store.
+#[derive(Clone, Copy)]
+enum Unlock<'a> {
+    MasterPassword,
+    RecoveryCode(&'a str),
+    DataKey(&'a [u8; KEY_LEN]),
+}
+pub const MASTER_SLOT_LABEL: &str = "master password";
 
 pub struct PasswordStore {
     pub file_path: PathBuf,
-    master_password: String,
+    master_password: Secret<String>,
+    keyfile: Option<Secret<Vec<u8>>>,
+    header: Option<StoreHeader>,
+    data_key: Option<Secret<[u8; KEY_LEN]>>,
+    unlocked_slot: Option<usize>,
+    rewrap_master: Option<Kdf>,
     passwords: Option<Passwords>,
+    loaded_hash: Option<[u8; 32]>,
+    /// Whether loading changed the store, by migrating it or giving entries ids, in a way that
+    /// only the next write saves.
+    unsaved_changes: bool,
+    lock: Option<StoreLock>,
+    lock_timeout: Duration,
 }
 
 impl PasswordStore {
-    pub fn new(file_path: PathBuf, master_password: String) -> anyhow::Result<Self> {
-        if !file_path.exists() || fs::metadata(&file_path)?.len() == 0 {
-            let salt = get_random_salt();
-            let (empty_json, nonce) = encrypt_contents(EMPTY_PASSWORDS, &master_password, &salt);
-            let mut content = salt.to_vec();
-            content.extend(nonce);
-            content.extend(empty_json);
-            fs::write(&file_path, content)?;
+    /// Opens the password store at `file_path`, creating it if needed. When a keyfile is
+    /// given, its contents are mixed into the key derivation of a new store's master password
+    /// slot, so both are needed to open it.
+    pub fn new(
+        file_path: PathBuf,
+        master_password: String,
+        keyfile: Option<PathBuf>,
+    ) -> Result<Self> {
+        Self::with_cipher(file_path, master_password, keyfile, Cipher::default())
+    }
+
+    /// Like `new`, but a new store is encrypted with `cipher`. Existing stores keep the cipher
+    /// recorded in their header.
+    pub fn with_cipher(
+        file_path: PathBuf,
+        master_password: String,
+        keyfile: Option<PathBuf>,
+        cipher: Cipher,
+    ) -> Result<Self> {
+        let master_password = Secret::new(master_password);
+        let keyfile = keyfile
+            .map(|path| read_keyfile(&path).map(Secret::new))
+            .transpose()?;
+        // Only creating the store needs the lock here, check again once it's held in case
+        // another process created the store in the meantime
+        let creation_lock = match is_new_store(&file_path)? {
+            true => Some(StoreLock::acquire(&file_path, DEFAULT_LOCK_TIMEOUT)?),
+            false => None,
+        };
+        if creation_lock.is_some() && is_new_store(&file_path)? {
+            let data_key = generate_key()?;
+            let secret = match keyfile.as_ref() {
+                Some(keyfile) => {
+                    SlotSecret::PasswordAndKeyfile(master_password.as_bytes(), keyfile)
+                }
+                None => SlotSecret::Password(master_password.as_bytes()),
+            };
+            let master_slot = KeySlot::new(
+                MASTER_SLOT_LABEL.to_string(),
+                Kdf::default(),
+                cipher,
+                secret,
+                &data_key,
+            )?;
+            let header = StoreHeader::new(cipher, vec![master_slot], cipher.generate_nonce()?);
+            let content = encrypt_store(&header, &data_key, EMPTY_PASSWORDS.as_bytes())?;
+            write_atomically(&file_path, &content)?;
         }
         let store = Self {
             file_path,
             master_password,
+            keyfile,
+            header: None,
+            data_key: None,
+            unlocked_slot: None,
+            rewrap_master: None,
             passwords: None,
+            loaded_hash: None,
+            unsaved_changes: false,
+            lock: None,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
         };
         Ok(store)
     }
 
-    pub fn load(&mut self) -> anyhow::Result<&mut Self> {
+    /// Reads and decrypts the store. This takes an exclusive lock on the store that is held
+    /// until the next `dump` or `release_lock`, so no other process can write to it in between.
+    pub fn load(&mut self) -> Result<&mut Self> {
+        self.load_with(Unlock::MasterPassword)
+    }
+
+    /// Unlocks the store with a one-time recovery code instead of the master password. The
+    /// code is burned and `new_master_password` replaces the master password, both taking
+    /// effect on the next dump.
+    pub fn recover(
+        &mut self,
+        recovery_code: &str,
+        new_master_password: String,
+    ) -> Result<&mut Self> {
+        let recovery_code = normalize_recovery_code(recovery_code);
+        self.load_with(Unlock::RecoveryCode(&recovery_code))?;
+        if let Some(index) = self.unlocked_slot.take() {
+            if let Some(StoreKey::Wrapped(slots)) =
+                self.header.as_mut().map(|header| &mut header.key)
+            {
+                slots.remove(index);
+            }
+        }
+        self.reset_master(new_master_password)
+    }
+
+    /// Unlocks the store with the data key recovered from enough of the shares made by
+    /// `split_data_key`.
+    pub fn load_from_shares(&mut self, shares: &[String]) -> Result<&mut Self> {
+        let data_key = key_from_slice(&combine_shares(shares)?).ok_or(LockboxError::WrongShares)?;
+        self.load_with(Unlock::DataKey(&data_key))
+    }
+
+    /// Takes the lock and unlocks the store with `method`, releasing the lock again if that
+    /// fails since the store can't be dumped then.
+    ///
+    /// Entries of older stores get their ids here. They are only kept in memory until the next
+    /// write, so loading never writes the store and read-only commands work on read-only files.
+    /// The ids are derived from the entries, so they are the same on every load until then.
+    fn load_with(&mut self, method: Unlock) -> Result<&mut Self> {
+        self.acquire_lock()?;
+        if let Err(err) = self
+            .unlock(method)
+            .and_then(|store| store.assign_missing_ids())
+        {
+            self.release_lock();
+            return Err(err);
+        }
+        Ok(self)
+    }
+
+    fn assign_missing_ids(&mut self) -> Result<()> {
+        if let Some(passwords) = self.passwords.as_mut() {
+            if passwords.assign_missing_ids() {
+                self.unsaved_changes = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether loading changed the store in a way that isn't written yet. See `load_with`.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.unsaved_changes
+    }
+
+    /// Splits the data key into `count` shares, any `threshold` of which unlock the store
+    /// through `load_from_shares`. Dump first if the store `has_unsaved_changes`, as a store
+    /// migrated on load gets a new data key that the shares hold.
+    pub fn split_data_key(&self, threshold: u8, count: u8) -> Result<Vec<String>> {
+        let data_key = self
+            .data_key
+            .as_ref()
+            .ok_or(LockboxError::NotLoaded("splitting the data key"))?;
+        split_secret(data_key.as_slice(), threshold, count)
+    }
+
+    /// Sets a new master password on a store that was unlocked without it. Unlike
+    /// `update_master`, a password + keyfile slot turns into a plain password slot when no
+    /// keyfile was given, since it may have been lost as well.
+    pub fn reset_master(&mut self, new_master_password: String) -> Result<&mut Self> {
+        self.master_password = Secret::new(new_master_password);
+        let kdf = self.kdf().unwrap_or_default();
+        self.rewrap_master_slot(kdf, false)?;
+        Ok(self)
+    }
+
+    /// Replaces the slots of all previous recovery codes with `count` new ones and returns the
+    /// codes. They are saved on the next dump.
+    pub fn generate_recovery_codes(&mut self, count: usize) -> Result<Vec<String>> {
+        let slots = match self.header.as_mut().map(|header| &mut header.key) {
+            Some(StoreKey::Wrapped(slots)) => slots,
+            _ => return Err(LockboxError::NotLoaded("generating recovery codes")),
+        };
+        if let Some(unlocked) = self.unlocked_slot {
+            let removed = slots[..unlocked]
+                .iter()
+                .filter(|slot| slot.kind == SlotKind::Recovery)
+                .count();
+            self.unlocked_slot =
+                (slots[unlocked].kind != SlotKind::Recovery).then_some(unlocked - removed);
+        }
+        slots.retain(|slot| slot.kind != SlotKind::Recovery);
+        if slots.len() + count > u8::MAX as usize {
+            return Err(too_many_slots());
+        }
+        let codes = (0..count)
+            .map(|_| generate_recovery_code())
+            .collect::<Result<Vec<String>>>()?;
+        for (number, code) in codes.iter().enumerate() {
+            self.add_slot(
+                format!("recovery code {}", number + 1),
+                SlotSecret::Recovery(normalize_recovery_code(code).as_bytes()),
+            )?;
+        }
+        Ok(codes)
+    }
+
+    fn unlock(&mut self, method: Unlock) -> Result<&mut Self> {
         let encrypted_file = std::fs::read(&self.file_path)?;
-        let salt = &encrypted_file[..16];
-        let cipher = get_cipher(&self.master_password, salt);
-        let nonce = &encrypted_file[16..28];
-        let encrypted_data = &encrypted_file[28..];
-        let plain_text = cipher
-            .decrypt(nonce.into(), encrypted_data.as_ref())
-            .map_err(|_| anyhow::anyhow!("Master password incorrect. Please try again."))?;
-        let plain_text_str = String::from_utf8(plain_text)?;
-        let parsed_passwords = Passwords::parse_passwords(&plain_text_str)?;
+        let (mut header, encrypted_data) = StoreHeader::parse(&encrypted_file)?;
+        let (mut data_key, mut unlocked_slot) = match (&header.key, method) {
+            (StoreKey::Derived { kdf, salt }, Unlock::MasterPassword) => {
+                (kdf.derive_key(self.master_password.as_bytes(), salt)?, None)
+            }
+            (StoreKey::Derived { .. }, Unlock::RecoveryCode(_)) => {
+                return Err(LockboxError::NoRecoveryCodes)
+            }
+            (StoreKey::Wrapped(slots), Unlock::MasterPassword) => {
+                let (index, data_key) = self.open_slot(header.cipher, slots)?;
+                (data_key, Some(index))
+            }
+            (StoreKey::Wrapped(slots), Unlock::RecoveryCode(recovery_code)) => {
+                let (index, data_key) = open_recovery_slot(header.cipher, slots, recovery_code)?;
+                (data_key, Some(index))
+            }
+            (StoreKey::Derived { .. }, Unlock::DataKey(_)) => {
+                return Err(LockboxError::WrongShares)
+            }
+            (StoreKey::Wrapped(_), Unlock::DataKey(data_key)) => (Zeroizing::new(*data_key), None),
+        };
+        let header_bytes = &encrypted_file[..encrypted_file.len() - encrypted_data.len()];
+        let aad = if header.is_authenticated() {
+            header_bytes
+        } else {
+            &[]
+        };
+        let plain_text = decrypt(header.cipher, &data_key, &header.nonce, encrypted_data, aad)
+            .map_err(|_| match (&header.key, method) {
+                (_, Unlock::DataKey(_)) => LockboxError::WrongShares,
+                // A key slot opened, so the data key is right and the file must have changed
+                (StoreKey::Wrapped(_), _) => LockboxError::Tampered,
+                (StoreKey::Derived { .. }, _) => LockboxError::WrongPassword,
+            })?;
+        let migrated = matches!(header.key, StoreKey::Derived { .. });
+        if let StoreKey::Derived { kdf, .. } = header.key {
+            // Older stores are moved to a random data key wrapped by a master password slot
+            data_key = generate_key()?;
+            let master_slot = KeySlot::new(
+                MASTER_SLOT_LABEL.to_string(),
+                kdf,
+                header.cipher,
+                SlotSecret::Password(self.master_password.as_bytes()),
+                &data_key,
+            )?;
+            header.key = StoreKey::Wrapped(vec![master_slot]);
+            unlocked_slot = Some(0);
+        }
+        let plain_text = std::str::from_utf8(&plain_text)
+            .map_err(|_| LockboxError::CorruptStore("passwords are not valid UTF-8".to_string()))?;
+        let parsed_passwords = Passwords::parse_passwords(plain_text)?;
+        self.header = Some(header);
+        self.data_key = Some(Secret::new(*data_key));
+        self.unlocked_slot = unlocked_slot;
         self.passwords = Some(parsed_passwords);
+        self.loaded_hash = Some(sha256(&encrypted_file));
+        self.unsaved_changes = migrated;
         Ok(self)
     }
 
-    pub fn dump(&mut self) -> anyhow::Result<&mut Self> {
-        let encrypted_file = std::fs::read(&self.file_path)?;
-        let salt = &encrypted_file[..16];
-        let cipher = get_cipher(&self.master_password, salt);
-        let nonce = &encrypted_file[16..28];
-        let plain_text = serde_json::to_string(&self.passwords)?;
-        let encrypted_text = cipher
-            .encrypt(nonce.into(), plain_text.as_ref())
-            .map_err(|e| anyhow::anyhow!("Failed to encrypt passwords: {:?}", e))?;
-        let mut content = salt.to_vec();
-        content.extend(nonce);
-        content.extend(encrypted_text);
-        std::fs::write(&self.file_path, content)?;
+    pub fn dump(&mut self) -> Result<&mut Self> {
+        self.write(true)
+    }
+
+    fn write(&mut self, backup: bool) -> Result<&mut Self> {
+        if let Some(kdf) = self.rewrap_master.take() {
+            self.rewrap_master_slot(kdf, true)?;
+        }
+        let (header, data_key) = match (self.header.as_mut(), self.data_key.as_ref()) {
+            (Some(header), Some(data_key)) => (header, data_key),
+            _ => return Err(LockboxError::NotLoaded("dumping")),
+        };
+        // Never reuse a nonce with the same key, every write gets a fresh one
+        header.nonce = header.cipher.generate_nonce()?;
+        header.version = header::CURRENT_VERSION;
+        let plain_text =
+            Zeroizing::new(serde_json::to_string(&self.passwords).map_err(std::io::Error::from)?);
+        let content = encrypt_store(header, data_key, plain_text.as_bytes())?;
+        self.acquire_lock()?;
+        // Don't overwrite what another process wrote since the store was loaded
+        let current_hash = fs::read(&self.file_path).ok().map(|file| sha256(&file));
+        if current_hash != self.loaded_hash {
+            self.release_lock();
+            return Err(LockboxError::ChangedOnDisk);
+        }
+        if backup {
+            rotate_backups(&self.file_path)?;
+        }
+        write_atomically(&self.file_path, &content)?;
+        self.loaded_hash = Some(sha256(&content));
+        self.unsaved_changes = false;
+        self.release_lock();
         Ok(self)
     }
 
+    /// Loads the store for a command that only reads from it. The lock is released when the
+    /// returned guard is dropped, however the command returns.
+    pub fn load_for_reading(&mut self) -> Result<LoadedStore<'_>> {
+        self.load()?;
+        Ok(LoadedStore(self))
+    }
+
+    /// Lets other processes write to the store again without dumping, for example after only
+    /// reading the passwords. The lock is also released when the store is dropped.
+    pub fn release_lock(&mut self) -> &mut Self {
+        self.lock = None;
+        self
+    }
+
+    fn acquire_lock(&mut self) -> Result<()> {
+        if self.lock.is_none() {
+            self.lock = Some(StoreLock::acquire(&self.file_path, self.lock_timeout)?);
+        }
+        Ok(())
+    }
+
     pub fn push(
         &mut self,
         service: String,
         username: Option<String>,
         password: String,
-    ) -> anyhow::Result<&mut Self> {
-        let new_password = PasswordEntry::new(service, username, password);
-        if let Some(ref mut passwords) = self.passwords {
-            passwords.append(new_password);
-        } else {
-            panic!("Load passwords before appending")
-        }
+    ) -> Result<&mut Self> {
+        self.push_entry(
+            PasswordEntry::new(service, username, password),
+            OnDuplicate::Fail,
+        )?;
         Ok(self)
     }
 
-    pub fn pop<W: Write>(
+    /// Adds an entry, giving it an id if it doesn't have one yet. See `Passwords::insert` for
+    /// what happens when its service and username are taken, and for what is returned.
+    pub fn push_entry(
         &mut self,
-        writer: &mut W,
-        service: String,
-        username: Option<String>,
-    ) -> &mut Self {
+        mut entry: PasswordEntry,
+        on_duplicate: OnDuplicate,
+    ) -> Result<&PasswordEntry> {
+        if entry.id.is_none() {
+            entry.id = Some(generate_id()?);
+        }
+        self.passwords
+            .as_mut()
+            .ok_or(LockboxError::NotLoaded("appending"))?
+            .insert(entry, on_duplicate)
+    }
+
+    /// Changes an entry in place. See `Passwords::edit`.
+    pub fn edit(&mut self, entry: &EntryRef, changes: EntryChanges) -> Result<&PasswordEntry> {
+        self.passwords
+            .as_mut()
+            .ok_or(LockboxError::NotLoaded("editing"))?
+            .edit(entry, changes)
+    }
+
+    /// Makes an earlier password of an entry current again. See
+    /// `PasswordEntry::restore_password`.
+    pub fn restore_password(&mut self, entry: &EntryRef, number: usize) -> Result<&PasswordEntry> {
+        let entry = self
+            .passwords
+            .as_mut()
+            .ok_or(LockboxError::NotLoaded("restoring a password"))?
+            .get_mut(entry)
+            .ok_or(LockboxError::EntryNotFound)?;
+        entry.restore_password(number)?;
+        Ok(entry)
+    }
+
+    /// Produces the next HOTP code of an entry and advances its counter. The store has to be
+    /// dumped before the code is used, or the next call gives the same code again.
+    pub fn next_hotp_code(&mut self, entry: &EntryRef) -> Result<String> {
+        let entry = self
+            .passwords
+            .as_mut()
+            .ok_or(LockboxError::NotLoaded("generating a code"))?
+            .get_mut(entry)
+            .ok_or(LockboxError::EntryNotFound)?;
+        let code = entry
+            .hotp
+            .as_mut()
+            .ok_or(LockboxError::NoHotp)?
+            .next_code()?;
+        entry.mark_used();
+        Ok(code)
+    }
+
+    /// Moves the HOTP counter of an entry past the codes a token showed. See `Hotp::resync`.
+    pub fn resync_hotp(&mut self, entry: &EntryRef, codes: &[String]) -> Result<u64> {
+        self.passwords
+            .as_mut()
+            .ok_or(LockboxError::NotLoaded("resyncing a counter"))?
+            .get_mut(entry)
+            .ok_or(LockboxError::EntryNotFound)?
+            .hotp
+            .as_mut()
+            .ok_or(LockboxError::NoHotp)?
+            .resync(codes)
+    }
+
+    /// Records that the password of an entry was just used and writes the store. Only the
+    /// timestamp changes, so the backups aren't rotated.
+    ///
+    /// Best effort: reading a password shouldn't fail because the store can't be written, for
+    /// example because the file is read-only or another process changed it, so a failed write
+    /// is ignored. The lock is released either way. A store that loading migrated, or that is
+    /// in an older format, isn't written at all, since the write would replace the original
+    /// file without a backup. The timestamp is saved with the next change instead.
+    pub fn mark_used(&mut self, entry: &EntryRef) -> &mut Self {
+        let outdated = self
+            .header
+            .as_ref()
+            .is_some_and(|header| header.version < header::CURRENT_VERSION);
+        let only_timestamp = !self.unsaved_changes && !outdated && self.rewrap_master.is_none();
+        let entry = self
+            .passwords
+            .as_mut()
+            .and_then(|passwords| passwords.get_mut(entry));
+        if let Some(entry) = entry {
+            entry.mark_used();
+            if only_timestamp {
+                let _ = self.write(false);
+            }
+        }
+        self.release_lock()
+    }
+
+    pub fn pop<W: Write>(&mut self, writer: &mut W, entry: &EntryRef) -> &mut Self {
         if let Some(_password) = self
             .passwords
             .as_mut()
-            .and_then(|passwords| passwords.remove(service, username))
+            .and_then(|passwords| passwords.remove(entry))
         {
             print(writer, "Password deleted", Some(MessageType::Success));
         } else {
@@ -100,34 +475,360 @@ impl PasswordStore {
     }
 
     pub fn find(&self, service: String, username: Option<String>) -> Option<&PasswordEntry> {
+        self.get(&EntryRef::name(service, username))
+    }
+
+    pub fn get(&self, entry: &EntryRef) -> Option<&PasswordEntry> {
         self.passwords
             .as_ref()
-            .and_then(|passwords| passwords.find(service, username))
+            .and_then(|passwords| passwords.get(entry))
     }
 
     pub fn print<W: Write>(
         &self,
         writer: &mut W,
         show_passwords: bool,
+        kind: Option<Kind>,
         message_type: Option<MessageType>,
     ) {
         if let Some(passwords) = self.passwords.as_ref() {
-            passwords.print_all(writer, show_passwords, message_type)
+            passwords.print_all(writer, show_passwords, kind, message_type)
         }
     }
 
+    /// Sets the master password. If the store is loaded, the next dump re-wraps the data key
+    /// for the master password slot with a fresh salt. The other key slots are left untouched.
     pub fn update_master(&mut self, new_master_password: String) -> &mut Self {
-        self.master_password = new_master_password;
+        self.master_password = Secret::new(new_master_password);
+        if self.header.is_some() {
+            self.rewrap_master = Some(self.kdf().unwrap_or_default());
+        }
         self
     }
+
+    /// The key derivation function of the master password slot.
+    pub fn kdf(&self) -> Option<Kdf> {
+        self.master_slot()
+            .and_then(|index| self.slots().get(index))
+            .map(|slot| slot.kdf)
+    }
+
+    /// Switches the key derivation function of the master password slot on the next dump.
+    pub fn update_kdf(&mut self, kdf: Kdf) -> Result<&mut Self> {
+        if self.header.is_none() {
+            return Err(LockboxError::NotLoaded("updating the KDF"));
+        }
+        self.rewrap_master = Some(kdf);
+        Ok(self)
+    }
+
+    pub fn slots(&self) -> &[KeySlot] {
+        match self.header.as_ref().map(|header| &header.key) {
+            Some(StoreKey::Wrapped(slots)) => slots,
+            _ => &[],
+        }
+    }
+
+    /// Index of the key slot the store was unlocked with.
+    pub fn unlocked_slot(&self) -> Option<usize> {
+        self.unlocked_slot
+    }
+
+    pub fn add_slot(&mut self, label: String, secret: SlotSecret) -> Result<&mut Self> {
+        let (header, data_key) = match (self.header.as_mut(), self.data_key.as_ref()) {
+            (Some(header), Some(data_key)) => (header, data_key),
+            _ => return Err(LockboxError::NotLoaded("adding a key slot")),
+        };
+        let slot = KeySlot::new(label, Kdf::default(), header.cipher, secret, data_key)?;
+        if let StoreKey::Wrapped(slots) = &mut header.key {
+            if slots.len() >= u8::MAX as usize {
+                return Err(too_many_slots());
+            }
+            slots.push(slot);
+        }
+        Ok(self)
+    }
+
+    pub fn remove_slot(&mut self, index: usize) -> Result<&mut Self> {
+        let slots = match self.header.as_mut().map(|header| &mut header.key) {
+            Some(StoreKey::Wrapped(slots)) => slots,
+            _ => return Err(LockboxError::NotLoaded("removing a key slot")),
+        };
+        if index >= slots.len() {
+            return Err(LockboxError::KeySlot(format!("Key slot {index} not found")));
+        }
+        if slots.len() == 1 {
+            return Err(LockboxError::KeySlot(
+                "Can't remove the last key slot, the password store would no longer open"
+                    .to_string(),
+            ));
+        }
+        slots.remove(index);
+        self.unlocked_slot = match self.unlocked_slot {
+            Some(unlocked) if unlocked == index => None,
+            Some(unlocked) if unlocked > index => Some(unlocked - 1),
+            unlocked => unlocked,
+        };
+        Ok(self)
+    }
+
+    /// The backups of this store that exist, from the most recent one (`store.bak.1`) on.
+    pub fn backups(&self) -> Vec<(usize, PathBuf)> {
+        (1..=BACKUP_COUNT)
+            .map(|number| (number, backup_path(&self.file_path, number)))
+            .filter(|(_, path)| path.exists())
+            .collect()
+    }
+
+    /// Replaces the store with backup `number` if it opens with the same master password and
+    /// keyfile. The current store becomes the most recent backup, so the restore can be undone.
+    /// Load again afterwards to read the restored passwords.
+    pub fn restore_backup(&mut self, number: usize) -> Result<&mut Self> {
+        let path = backup_path(&self.file_path, number);
+        if number == 0 || number > BACKUP_COUNT || !path.exists() {
+            return Err(LockboxError::BackupNotFound(number));
+        }
+        let mut backup = Self {
+            file_path: path.clone(),
+            master_password: self.master_password.clone(),
+            keyfile: self.keyfile.clone(),
+            header: None,
+            data_key: None,
+            unlocked_slot: None,
+            rewrap_master: None,
+            passwords: None,
+            loaded_hash: None,
+            unsaved_changes: false,
+            lock: None,
+            lock_timeout: self.lock_timeout,
+        };
+        backup
+            .unlock(Unlock::MasterPassword)
+            .map_err(|err| LockboxError::InvalidBackup {
+                number,
+                source: Box::new(err),
+            })?;
+        let content = fs::read(&path)?;
+        self.acquire_lock()?;
+        rotate_backups(&self.file_path)?;
+        write_atomically(&self.file_path, &content)?;
+        self.header = None;
+        self.data_key = None;
+        self.unlocked_slot = None;
+        self.rewrap_master = None;
+        self.passwords = None;
+        self.loaded_hash = None;
+        self.release_lock();
+        Ok(self)
+    }
+
+    /// The slot unlocked by the master password, or the first password slot otherwise.
+    fn master_slot(&self) -> Option<usize> {
+        let slots = self.slots();
+        let is_password_slot =
+            |slot: &KeySlot| matches!(slot.kind, SlotKind::Password | SlotKind::PasswordAndKeyfile);
+        self.unlocked_slot
+            .filter(|&index| is_password_slot(&slots[index]))
+            .or_else(|| slots.iter().position(is_password_slot))
+    }
+
+    /// Wraps the data key for the current master password and replaces the master password
+    /// slot with it, or adds one. A password + keyfile slot keeps needing the keyfile, unless
+    /// `require_keyfile` is false and no keyfile was given.
+    fn rewrap_master_slot(&mut self, kdf: Kdf, require_keyfile: bool) -> Result<()> {
+        let master_slot = self.master_slot();
+        let (label, kind) = master_slot
+            .and_then(|index| self.slots().get(index))
+            .map_or(
+                (MASTER_SLOT_LABEL.to_string(), SlotKind::Password),
+                |slot| (slot.label.clone(), slot.kind),
+            );
+        let (header, data_key) = match (self.header.as_mut(), self.data_key.as_ref()) {
+            (Some(header), Some(data_key)) => (header, data_key),
+            _ => return Err(LockboxError::NotLoaded("updating the master password")),
+        };
+        let secret = match (kind, self.keyfile.as_ref()) {
+            (SlotKind::PasswordAndKeyfile, Some(keyfile)) => {
+                SlotSecret::PasswordAndKeyfile(self.master_password.as_bytes(), keyfile)
+            }
+            (SlotKind::PasswordAndKeyfile, None) if require_keyfile => {
+                return Err(LockboxError::KeyfileRequired)
+            }
+            _ => SlotSecret::Password(self.master_password.as_bytes()),
+        };
+        let slot = KeySlot::new(label, kdf, header.cipher, secret, data_key)?;
+        if let StoreKey::Wrapped(slots) = &mut header.key {
+            match master_slot {
+                Some(index) => slots[index] = slot,
+                None => {
+                    slots.push(slot);
+                    self.unlocked_slot = Some(slots.len() - 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tries every slot with the secrets at hand. If none opens, a missing or wrong keyfile is
+    /// reported before a wrong master password, unless the keyfile was right for some slot.
+    fn open_slot(&self, cipher: Cipher, slots: &[KeySlot]) -> Result<(usize, Key)> {
+        let master_password = self.master_password.as_bytes();
+        let (mut keyfile_missing, mut keyfile_rejected, mut keyfile_accepted) =
+            (false, false, false);
+        for (index, slot) in slots.iter().enumerate() {
+            let secret = match (slot.kind, self.keyfile.as_deref()) {
+                (SlotKind::Password, _) => SlotSecret::Password(master_password),
+                (SlotKind::Keyfile, Some(keyfile)) => SlotSecret::Keyfile(keyfile),
+                // Recovery codes only open the store through `recover`
+                (SlotKind::Keyfile, None) | (SlotKind::Recovery, _) => continue,
+                (SlotKind::PasswordAndKeyfile, Some(keyfile)) => {
+                    SlotSecret::PasswordAndKeyfile(master_password, keyfile)
+                }
+                (SlotKind::PasswordAndKeyfile, None) => {
+                    keyfile_missing = true;
+                    continue;
+                }
+            };
+            if let Some(data_key) = slot.unwrap_key(cipher, secret)? {
+                return Ok((index, data_key));
+            }
+            // Checked only once the slot didn't open, as it takes another key derivation
+            if let SlotSecret::PasswordAndKeyfile(_, keyfile) = secret {
+                if slot.keyfile_matches(keyfile)? {
+                    keyfile_accepted = true;
+                } else {
+                    keyfile_rejected = true;
+                }
+            }
+        }
+        if !keyfile_accepted && keyfile_rejected {
+            Err(LockboxError::WrongKeyfile)
+        } else if !keyfile_accepted && keyfile_missing {
+            Err(LockboxError::KeyfileRequired)
+        } else {
+            Err(LockboxError::WrongPassword)
+        }
+    }
+}
+
+fn open_recovery_slot(
+    cipher: Cipher,
+    slots: &[KeySlot],
+    recovery_code: &str,
+) -> Result<(usize, Key)> {
+    for (index, slot) in slots.iter().enumerate() {
+        let secret = SlotSecret::Recovery(recovery_code.as_bytes());
+        if let Some(data_key) = slot.unwrap_key(cipher, secret)? {
+            return Ok((index, data_key));
+        }
+    }
+    Err(LockboxError::WrongRecoveryCode)
+}
+
+/// A loaded store that releases its lock when dropped. See `PasswordStore::load_for_reading`.
+pub struct LoadedStore<'a>(&'a mut PasswordStore);
+
+impl Deref for LoadedStore<'_> {
+    type Target = PasswordStore;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl DerefMut for LoadedStore<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl Drop for LoadedStore<'_> {
+    fn drop(&mut self) {
+        self.0.release_lock();
+    }
+}
+
+fn too_many_slots() -> LockboxError {
+    LockboxError::KeySlot("The password store has too many key slots".to_string())
+}
+
+fn read_keyfile(path: &Path) -> Result<Vec<u8>> {
+    let invalid_keyfile = |reason: String| LockboxError::InvalidKeyfile {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let keyfile = fs::read(path).map_err(|err| invalid_keyfile(err.to_string()))?;
+    if keyfile.is_empty() {
+        return Err(invalid_keyfile("the keyfile is empty".to_string()));
+    }
+    Ok(keyfile)
+}
+
+fn encrypt_store(
+    header: &StoreHeader,
+    data_key: &[u8; KEY_LEN],
+    plain_text: &[u8],
+) -> Result<Vec<u8>> {
+    let mut content = header.to_bytes()?;
+    let encrypted_text = encrypt(header.cipher, data_key, &header.nonce, plain_text, &content)?;
+    content.extend(encrypted_text);
+    Ok(content)
+}
+
+fn is_new_store(file_path: &Path) -> Result<bool> {
+    Ok(!file_path.exists() || fs::metadata(file_path)?.len() == 0)
+}
+
+pub fn backup_path(file_path: &Path, number: usize) -> PathBuf {
+    let mut file_name = file_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".bak.{number}"));
+    file_path.with_file_name(file_name)
+}
+
+/// Shifts `store.bak.1`… up by one, dropping the oldest, and copies the store to
+/// `store.bak.1`.
+fn rotate_backups(file_path: &Path) -> Result<()> {
+    if !file_path.exists() {
+        return Ok(());
+    }
+    for number in (1..BACKUP_COUNT).rev() {
+        let path = backup_path(file_path, number);
+        if path.exists() {
+            fs::rename(&path, backup_path(file_path, number + 1))?;
+        }
+    }
+    write_atomically(&backup_path(file_path, 1), &fs::read(file_path)?)
+}
+
+/// Writes to a temporary file next to `path`, fsyncs it and renames it over `path`, so a crash
+/// or a full disk midway leaves either the old or the new contents on disk, never a truncated
+/// file.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+    temp_file.write_all(content)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path)?;
+    // The rename itself is only durable once the directory entry is flushed
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::cli::{commands::add_password, io::MockPromptPassword};
+    use crate::cli::{
+        commands::{add_password, show_password},
+        io::MockPromptPassword,
+    };
+    use crate::pass::EntryDetails;
+    use header::MAX_LABEL_LEN;
     use passwords::PasswordGenerator;
     use rstest::rstest;
-    use tempfile::NamedTempFile;
+    use tempfile::tempdir;
 
     use super::*;
     const TEST_MASTER_PASSWORD: &str = "test_master";
@@ -136,9 +837,9 @@ mod tests {
     fn test_new_password_store() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let store =
-            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
         assert_eq!(store.file_path, temp_file);
-        assert_eq!(store.master_password, TEST_MASTER_PASSWORD);
+        assert_eq!(store.master_password.as_str(), TEST_MASTER_PASSWORD);
         assert!(store.passwords.is_none());
         assert!(temp_file.exists());
     }
@@ -147,9 +848,9 @@ mod tests {
     fn test_new_password_store_with_nonexistent_file() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let store =
-            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string()).unwrap();
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
         assert_eq!(store.file_path, temp_file);
-        assert_eq!(store.master_password, TEST_MASTER_PASSWORD);
+        assert_eq!(store.master_password.as_str(), TEST_MASTER_PASSWORD);
         assert!(store.passwords.is_none());
         assert!(PathBuf::from(temp_file.to_str().unwrap()).exists());
     }
@@ -181,7 +882,8 @@ mod tests {
     )]
     fn test_load_after_store_passwords(#[case] test_passwords: Vec<PasswordEntry>) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut store = PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
         store.load().unwrap();
         test_passwords.iter().for_each(|test_password| {
             store
@@ -267,7 +969,8 @@ mod tests {
         expect_password_found: bool,
     ) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
-        let mut store = PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string()).unwrap();
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
         store.load().unwrap();
         store.passwords = Some(test_passwords.into());
         let found_password = store.find(service.to_string(), username.map(|u| u.to_string()));
@@ -280,8 +983,13 @@ mod tests {
                 assert_eq!(found_password.username, None);
             }
         }
-        if let Some(expected_password) = expected_password {
-            assert_eq!(found_password, Some(&expected_password));
+        if let (Some(mut expected_password), Some(found_password)) =
+            (expected_password, found_password)
+        {
+            // The entries were created separately, possibly in different seconds
+            expected_password.created = found_password.created;
+            expected_password.modified = found_password.modified;
+            assert_eq!(found_password, &expected_password);
         } else {
             assert_eq!(found_password, None);
         }
@@ -309,7 +1017,7 @@ mod tests {
     ) {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut password_store =
-            PasswordStore::new(temp_file, "master_password".to_string()).unwrap();
+            PasswordStore::new(temp_file, "master_password".to_string(), None).unwrap();
         let mut writer = std::io::Cursor::new(Vec::new());
         let mock_prompt_password = &MockPromptPassword::new();
         passwords
@@ -324,13 +1032,15 @@ mod tests {
                     Some(password.to_string()),
                     false,
                     PasswordGenerator::default(),
+                    EntryDetails::default(),
+                    OnDuplicate::Fail,
                 )
                 .unwrap()
             });
 
         let mut output = Vec::new();
         let mut writer = std::io::Cursor::new(output);
-        password_store.print(&mut writer, show_passwords, Some(MessageType::Info));
+        password_store.print(&mut writer, show_passwords, None, Some(MessageType::Info));
 
         output = writer.into_inner();
         let output_str = String::from_utf8(output).unwrap();
@@ -339,13 +1049,718 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_new_password_store_writes_header() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let contents = fs::read(&temp_file).unwrap();
+        let (header, _) = StoreHeader::parse(&contents).unwrap();
+        assert!(contents.starts_with(header::MAGIC));
+        assert_eq!(header.version, header::CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_load_headerless_store() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let kdf = Kdf::Pbkdf2 {
+            iterations: crate::crypto::PBKDF2_ITERATIONS,
+        };
+        let salt = crate::crypto::get_random_salt().unwrap();
+        let key = kdf
+            .derive_key(TEST_MASTER_PASSWORD.as_bytes(), &salt)
+            .unwrap();
+        let nonce = Cipher::Aes256Gcm.generate_nonce().unwrap();
+        let encrypted_json = encrypt(
+            Cipher::Aes256Gcm,
+            &key,
+            &nonce,
+            EMPTY_PASSWORDS.as_bytes(),
+            &[],
+        )
+        .unwrap();
+        let mut content = salt.to_vec();
+        content.extend(nonce);
+        content.extend(encrypted_json);
+        fs::write(&temp_file, content).unwrap();
+
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap();
+        // Migrated to a new data key that only the next write saves
+        assert!(store.has_unsaved_changes());
+        store
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let contents = fs::read(&temp_file).unwrap();
+        assert!(contents.starts_with(header::MAGIC));
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap();
+        assert!(store.find("service".to_string(), None).is_some());
+        assert_eq!(store.kdf(), Some(kdf));
+        assert_eq!(store.slots().len(), 1);
+        assert_ne!(store.data_key.as_deref(), Some(&*key));
+    }
+
+    #[test]
+    fn test_showing_a_password_keeps_an_older_store() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let kdf = Kdf::Pbkdf2 {
+            iterations: crate::crypto::PBKDF2_ITERATIONS,
+        };
+        let salt = crate::crypto::get_random_salt().unwrap();
+        let key = kdf
+            .derive_key(TEST_MASTER_PASSWORD.as_bytes(), &salt)
+            .unwrap();
+        let nonce = Cipher::Aes256Gcm.generate_nonce().unwrap();
+        let mut content = header::MAGIC.to_vec();
+        content.push(1);
+        header::write_kdf(&mut content, kdf);
+        header::write_bytes(&mut content, &salt).unwrap();
+        header::write_cipher(&mut content, Cipher::Aes256Gcm);
+        content.extend(&nonce);
+        content.extend(
+            encrypt(
+                Cipher::Aes256Gcm,
+                &key,
+                &nonce,
+                br#"[{"service":"service","username":null,"password":"password"}]"#,
+                &[],
+            )
+            .unwrap(),
+        );
+        fs::write(&temp_file, &content).unwrap();
+
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        show_password(
+            &mut Vec::new(),
+            &mut store,
+            EntryRef::name("service".to_string(), None),
+            false,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&temp_file).unwrap(), content);
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_load_assigns_missing_ids() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap();
+        // Entries written before ids existed
+        store.passwords = Some(
+            Passwords::parse_passwords(
+                r#"[{"service":"service1","username":null,"password":"password1"},
+                    {"service":"service2","username":"username2","password":"password2"}]"#,
+            )
+            .unwrap(),
+        );
+        store.dump().unwrap();
+
+        let ids = |store: &PasswordStore| {
+            [
+                store.find("service1".to_string(), None).unwrap().id,
+                store
+                    .find("service2".to_string(), Some("username2".to_string()))
+                    .unwrap()
+                    .id,
+            ]
+        };
+        let contents = fs::read(&temp_file).unwrap();
+        // The dump above made the first backup
+        let backups = backup_path(&temp_file, 2);
+        assert!(!backups.exists());
+
+        // Loading only assigns the ids in memory, without writing the store
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let first_ids = ids(store.load().unwrap());
+        assert!(first_ids.iter().all(Option::is_some));
+        assert_ne!(first_ids[0], first_ids[1]);
+        assert!(store.has_unsaved_changes());
+        store.release_lock();
+        assert_eq!(fs::read(&temp_file).unwrap(), contents);
+        assert!(!backups.exists());
+        assert_eq!(ids(store.load().unwrap()), first_ids);
+
+        // The next write saves them
+        store.dump().unwrap();
+        assert!(!store.has_unsaved_changes());
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert_eq!(ids(store.load().unwrap()), first_ids);
+        assert!(!store.has_unsaved_changes());
+        let id = first_ids[1].unwrap();
+        assert_eq!(store.get(&EntryRef::Id(id)).unwrap().service, "service2");
+
+        store
+            .push("service3".to_string(), None, "password3".to_string())
+            .unwrap();
+        assert!(store
+            .find("service3".to_string(), None)
+            .unwrap()
+            .id
+            .is_some());
+        store.pop(&mut Vec::new(), &EntryRef::Id(id));
+        assert!(store.get(&EntryRef::Id(id)).is_none());
+        store.release_lock();
+    }
+
+    #[test]
+    fn test_update_kdf() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert!(store.update_kdf(Kdf::default()).is_err());
+        let kdf = Kdf::Pbkdf2 { iterations: 1000 };
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .update_kdf(kdf)
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.kdf(), Some(kdf));
+        assert!(store.find("service".to_string(), None).is_some());
+    }
+
+    #[test]
+    fn test_dump_uses_fresh_nonce() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let read_nonce = || {
+            let contents = fs::read(&temp_file).unwrap();
+            StoreHeader::parse(&contents).unwrap().0.nonce
+        };
+        let initial_nonce = read_nonce();
+        store.load().unwrap().dump().unwrap();
+        let first_nonce = read_nonce();
+        store.dump().unwrap();
+        let second_nonce = read_nonce();
+        assert_ne!(initial_nonce, first_nonce);
+        assert_ne!(first_nonce, second_nonce);
+        assert!(store.load().is_ok());
+    }
+
+    #[test]
+    fn test_update_master_re_keys_store() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        store
+            .add_slot(
+                "backup".to_string(),
+                SlotSecret::Password(b"backup_password"),
+            )
+            .unwrap()
+            .dump()
+            .unwrap();
+        let old_header = StoreHeader::parse(&fs::read(&temp_file).unwrap())
+            .unwrap()
+            .0;
+
+        store
+            .update_master("new_master_password".to_string())
+            .dump()
+            .unwrap();
+        let new_header = StoreHeader::parse(&fs::read(&temp_file).unwrap())
+            .unwrap()
+            .0;
+        let (StoreKey::Wrapped(old_slots), StoreKey::Wrapped(new_slots)) =
+            (old_header.key, new_header.key)
+        else {
+            panic!("Expected wrapped data keys");
+        };
+        assert_ne!(old_slots[0].salt, new_slots[0].salt);
+        assert_eq!(old_slots[1], new_slots[1]);
+        assert_ne!(old_header.nonce, new_header.nonce);
+        let leftover_files = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                let name = name.to_string_lossy();
+                !name.contains(".bak.") && !name.ends_with(".lock")
+            })
+            .count();
+        assert_eq!(leftover_files, 1);
+
+        let mut old_store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert!(old_store.load().is_err());
+        let mut new_store =
+            PasswordStore::new(temp_file.clone(), "new_master_password".to_string(), None).unwrap();
+        assert!(new_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_some());
+        new_store.release_lock();
+        let mut backup_store =
+            PasswordStore::new(temp_file, "backup_password".to_string(), None).unwrap();
+        assert!(backup_store.load().is_ok());
+    }
+
+    #[test]
+    fn test_add_and_remove_slots() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let keyfile_path = temp_dir.path().join("keyfile");
+        fs::write(&keyfile_path, b"keyfile contents").unwrap();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert!(store
+            .add_slot(
+                "second".to_string(),
+                SlotSecret::Password(b"second_password")
+            )
+            .is_err());
+        store
+            .load()
+            .unwrap()
+            .add_slot(
+                "second".to_string(),
+                SlotSecret::Password(b"second_password"),
+            )
+            .unwrap()
+            .add_slot("usb".to_string(), SlotSecret::Keyfile(b"keyfile contents"))
+            .unwrap()
+            .dump()
+            .unwrap();
+        assert_eq!(store.slots().len(), 3);
+
+        let mut second_store =
+            PasswordStore::new(temp_file.clone(), "second_password".to_string(), None).unwrap();
+        second_store.load().unwrap().release_lock();
+        assert_eq!(second_store.unlocked_slot(), Some(1));
+        let mut no_keyfile_store =
+            PasswordStore::new(temp_file.clone(), String::new(), None).unwrap();
+        assert!(no_keyfile_store.load().is_err());
+        let mut keyfile_store =
+            PasswordStore::new(temp_file.clone(), String::new(), Some(keyfile_path)).unwrap();
+        keyfile_store.load().unwrap().release_lock();
+        assert_eq!(keyfile_store.unlocked_slot(), Some(2));
+
+        assert!(store.remove_slot(3).is_err());
+        store.remove_slot(1).unwrap().dump().unwrap();
+        assert!(second_store.load().is_err());
+        assert!(keyfile_store.load().is_ok());
+        store.remove_slot(1).unwrap();
+        assert!(store.remove_slot(0).is_err());
+    }
+
+    #[test]
+    fn test_add_slot_rejects_long_label() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let result = store.load().unwrap().add_slot(
+            "a".repeat(MAX_LABEL_LEN + 1),
+            SlotSecret::Password(b"second_password"),
+        );
+        assert!(matches!(result, Err(LockboxError::KeySlot(_))));
+        store
+            .add_slot(
+                "a".repeat(MAX_LABEL_LEN),
+                SlotSecret::Password(b"second_password"),
+            )
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let mut reopened =
+            PasswordStore::new(temp_file, "second_password".to_string(), None).unwrap();
+        reopened.load().unwrap().release_lock();
+        assert_eq!(reopened.slots()[1].label.len(), MAX_LABEL_LEN);
+    }
+
+    #[test]
+    fn test_password_and_keyfile_store() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let keyfile_path = temp_dir.path().join("keyfile");
+        let wrong_keyfile_path = temp_dir.path().join("wrong_keyfile");
+        fs::write(&keyfile_path, b"keyfile contents").unwrap();
+        fs::write(&wrong_keyfile_path, b"other contents").unwrap();
+        let open = |master: &str, keyfile: Option<&PathBuf>| {
+            let mut store =
+                PasswordStore::new(temp_file.clone(), master.to_string(), keyfile.cloned())?;
+            store.load()?.release_lock();
+            Ok::<_, LockboxError>(store)
+        };
+
+        let mut store = open(TEST_MASTER_PASSWORD, Some(&keyfile_path)).unwrap();
+        assert_eq!(store.slots()[0].kind, SlotKind::PasswordAndKeyfile);
+        store
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let err = open(TEST_MASTER_PASSWORD, None).err().unwrap();
+        assert!(matches!(err, LockboxError::KeyfileRequired));
+        let err = open(TEST_MASTER_PASSWORD, Some(&wrong_keyfile_path))
+            .err()
+            .unwrap();
+        assert!(matches!(err, LockboxError::WrongKeyfile));
+        let err = open("wrong_master", Some(&keyfile_path)).err().unwrap();
+        assert!(matches!(err, LockboxError::WrongPassword));
+        let err = open(TEST_MASTER_PASSWORD, Some(&temp_dir.path().join("missing")))
+            .err()
+            .unwrap();
+        assert!(matches!(err, LockboxError::InvalidKeyfile { .. }));
+
+        store
+            .update_master("new_master_password".to_string())
+            .dump()
+            .unwrap();
+        let store = open("new_master_password", Some(&keyfile_path)).unwrap();
+        assert_eq!(store.slots()[0].kind, SlotKind::PasswordAndKeyfile);
+        assert!(store.find("service".to_string(), None).is_some());
+        assert!(open("new_master_password", None).is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let keyfile_path = temp_dir.path().join("keyfile");
+        fs::write(&keyfile_path, b"keyfile contents").unwrap();
+        let mut store = PasswordStore::new(
+            temp_file.clone(),
+            TEST_MASTER_PASSWORD.to_string(),
+            Some(keyfile_path),
+        )
+        .unwrap();
+        assert!(store.generate_recovery_codes(1).is_err());
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap();
+        let old_codes = store.generate_recovery_codes(2).unwrap();
+        let codes = store.generate_recovery_codes(3).unwrap();
+        store.dump().unwrap();
+        assert_eq!(store.slots().len(), 4);
+        assert_eq!(store.unlocked_slot(), Some(0));
+
+        let mut recovery_store =
+            PasswordStore::new(temp_file.clone(), String::new(), None).unwrap();
+        assert!(recovery_store.load().is_err());
+        let err = recovery_store
+            .recover(&old_codes[0], "new_master".to_string())
+            .err()
+            .unwrap();
+        assert!(matches!(err, LockboxError::WrongRecoveryCode));
+        recovery_store
+            .recover(&codes[1].to_lowercase(), "new_master".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        assert_eq!(recovery_store.slots().len(), 3);
+        assert!(recovery_store
+            .recover(&codes[1], "other_master".to_string())
+            .is_err());
+
+        let mut new_store =
+            PasswordStore::new(temp_file.clone(), "new_master".to_string(), None).unwrap();
+        new_store.load().unwrap();
+        assert_eq!(new_store.slots()[0].kind, SlotKind::Password);
+        assert!(new_store.find("service".to_string(), None).is_some());
+        new_store.release_lock();
+        let mut old_store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert!(old_store
+            .recover(&codes[0], "new_master".to_string())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_xchacha20_poly1305_store() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store = PasswordStore::with_cipher(
+            temp_file.clone(),
+            TEST_MASTER_PASSWORD.to_string(),
+            None,
+            Cipher::XChaCha20Poly1305,
+        )
+        .unwrap();
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let header = StoreHeader::parse(&fs::read(&temp_file).unwrap())
+            .unwrap()
+            .0;
+        assert_eq!(header.cipher, Cipher::XChaCha20Poly1305);
+        assert_eq!(header.nonce.len(), 24);
+        let mut store = PasswordStore::with_cipher(
+            temp_file,
+            TEST_MASTER_PASSWORD.to_string(),
+            None,
+            Cipher::Aes256Gcm,
+        )
+        .unwrap();
+        assert!(store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_some());
+    }
+
+    #[rstest(
+        tamper,
+        // The master slot label comes after magic, version, cipher, slot count, kind and length
+        case::label(|contents: &mut Vec<u8>, _: &StoreHeader| contents[9] ^= 1),
+        case::version(|contents: &mut Vec<u8>, _: &StoreHeader| contents[4] = 2),
+        case::ciphertext(|contents: &mut Vec<u8>, _: &StoreHeader| {
+            let last = contents.len() - 1;
+            contents[last] ^= 1;
+        }),
+        case::nonce(|contents: &mut Vec<u8>, header: &StoreHeader| {
+            let nonce_start = header.to_bytes().unwrap().len() - header.nonce.len();
+            contents[nonce_start] ^= 1;
+        })
+    )]
+    fn test_load_detects_tampering(tamper: fn(&mut Vec<u8>, &StoreHeader)) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap().dump().unwrap();
+        let mut contents = fs::read(&temp_file).unwrap();
+        let header = StoreHeader::parse(&contents).unwrap().0;
+        assert!(header.is_authenticated());
+        tamper(&mut contents, &header);
+        fs::write(&temp_file, contents).unwrap();
+
+        let err = store.load().err().unwrap();
+        assert!(matches!(err, LockboxError::Tampered));
+    }
+
+    #[rstest(
+        keep,
+        case::empty(|_: usize| 0),
+        case::inside_magic(|_: usize| 2),
+        case::inside_header(|header_len: usize| header_len / 2),
+        case::no_ciphertext(|header_len: usize| header_len),
+        case::inside_ciphertext(|header_len: usize| header_len + 4)
+    )]
+    fn test_load_truncated_store(keep: fn(usize) -> usize) {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap().dump().unwrap();
+        let contents = fs::read(&temp_file).unwrap();
+        let header_len = StoreHeader::parse(&contents)
+            .unwrap()
+            .0
+            .to_bytes()
+            .unwrap()
+            .len();
+        fs::write(&temp_file, &contents[..keep(header_len)]).unwrap();
+
+        let err = store.load().err().unwrap();
+        assert!(matches!(
+            err,
+            LockboxError::CorruptStore(_) | LockboxError::Tampered
+        ));
+        assert!(store.lock.is_none());
+    }
+
+    #[test]
+    fn test_push_before_load() {
+        let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
+        let mut store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let err = store
+            .push("service".to_string(), None, "password".to_string())
+            .err()
+            .unwrap();
+        assert!(matches!(err, LockboxError::NotLoaded("appending")));
+    }
+
+    #[test]
+    fn test_backups_rotate_and_restore() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        assert!(store.backups().is_empty());
+        store.load().unwrap();
+        for index in 0..BACKUP_COUNT + 2 {
+            store
+                .push(format!("service_{index}"), None, "password".to_string())
+                .unwrap()
+                .dump()
+                .unwrap();
+        }
+        let backups = store.backups();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        assert_eq!(backups[0], (1, temp_dir.path().join("store.bak.1")));
+        assert!(!backup_path(&temp_file, BACKUP_COUNT + 1).exists());
+
+        // Backup 2 was written before service_5 and service_6 were added
+        store.restore_backup(2).unwrap().load().unwrap();
+        assert!(store.find("service_4".to_string(), None).is_some());
+        assert!(store.find("service_5".to_string(), None).is_none());
+        // The store before the restore is now the most recent backup
+        store.restore_backup(1).unwrap().load().unwrap();
+        assert!(store.find("service_6".to_string(), None).is_some());
+
+        assert!(store.restore_backup(BACKUP_COUNT + 1).is_err());
+        let mut other_store =
+            PasswordStore::new(temp_file, "other_master".to_string(), None).unwrap();
+        let err = other_store.restore_backup(1).err().unwrap();
+        assert!(matches!(err, LockboxError::InvalidBackup { number: 1, .. }));
+    }
+
+    #[test]
+    fn test_load_holds_lock_until_dump() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let mut other_store =
+            PasswordStore::new(temp_file, TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.lock_timeout = Duration::ZERO;
+        other_store.lock_timeout = Duration::ZERO;
+
+        store.load().unwrap();
+        let err = other_store.load().err().unwrap();
+        assert!(
+            matches!(err, LockboxError::Locked { pid: Some(pid) } if pid == std::process::id())
+        );
+        store
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        assert!(other_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_some());
+
+        assert!(store.load().is_err());
+        other_store.release_lock();
+        assert!(store.load().is_ok());
+    }
+
+    #[test]
+    fn test_dump_detects_external_changes() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let mut other_store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store.load().unwrap().release_lock();
+        other_store
+            .load()
+            .unwrap()
+            .push("other_service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        let err = store
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .err()
+            .unwrap();
+        assert!(matches!(err, LockboxError::ChangedOnDisk));
+        assert!(other_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .is_none());
+        other_store.release_lock();
+
+        // Dumping again after a dump is fine, the store remembers what it wrote
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap()
+            .dump()
+            .unwrap();
+        assert!(store.find("other_service".to_string(), None).is_some());
+    }
+
+    #[test]
+    fn test_mark_used_ignores_failed_writes() {
+        let temp_dir = tempdir().unwrap();
+        let temp_file = temp_dir.path().join("store");
+        let mut store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        let mut other_store =
+            PasswordStore::new(temp_file.clone(), TEST_MASTER_PASSWORD.to_string(), None).unwrap();
+        store
+            .load()
+            .unwrap()
+            .push("service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+        store.load().unwrap().release_lock();
+        other_store
+            .load()
+            .unwrap()
+            .push("other_service".to_string(), None, "password".to_string())
+            .unwrap()
+            .dump()
+            .unwrap();
+
+        store.mark_used(&EntryRef::name("service".to_string(), None));
+        assert!(StoreLock::acquire(&temp_file, Duration::ZERO).is_ok());
+        let entry = other_store
+            .load()
+            .unwrap()
+            .find("service".to_string(), None)
+            .unwrap();
+        assert!(entry.last_used.is_none());
+        assert!(other_store
+            .find("other_service".to_string(), None)
+            .is_some());
+        other_store.release_lock();
+    }
+
     #[test]
     fn test_update_master() {
         let temp_file = NamedTempFile::new().unwrap().path().to_path_buf();
         let mut password_store =
-            PasswordStore::new(temp_file, "some_master_password".to_string()).unwrap();
+            PasswordStore::new(temp_file, "some_master_password".to_string(), None).unwrap();
         password_store.update_master("new_master_password".to_string());
-        assert!(password_store.master_password == "new_master_password");
+        assert!(password_store.master_password.as_str() == "new_master_password");
         assert!(password_store.load().is_err());
         if let Err(err) = password_store.load() {
             err.to_string()